@@ -0,0 +1,55 @@
+//! Compares `IdentityBuildHasher` with the default hasher on `RefCmp<Arc<T>>`
+//! sets.
+//!
+//! Run with `cargo bench`.
+
+extern crate same;
+
+use same::{IdentityBuildHasher, RefCmp, RefKey};
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::BuildHasher;
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const OBJECTS: usize = 10_000;
+const ROUNDS: u32 = 100;
+
+fn bench<S: BuildHasher + Default>(name: &str, objects: &[Arc<u64>]) {
+    let mut insert = Duration::default();
+    let mut lookup = Duration::default();
+
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let mut set = HashSet::with_capacity_and_hasher(objects.len(), S::default());
+        for object in objects {
+            set.insert(RefCmp(object.clone()));
+        }
+        insert += start.elapsed();
+
+        let start = Instant::now();
+        let mut found = 0;
+        for object in objects {
+            // Looking up by `RefKey` avoids touching the reference counts.
+            if set.contains(RefKey::of(object)) {
+                found += 1;
+            }
+        }
+        lookup += start.elapsed();
+        assert_eq!(black_box(found), objects.len());
+    }
+
+    let ops = u64::from(ROUNDS) * objects.len() as u64;
+    println!("{:<24} insert: {:>6} ns/op, lookup: {:>6} ns/op",
+             name,
+             insert.as_nanos() as u64 / ops,
+             lookup.as_nanos() as u64 / ops);
+}
+
+fn main() {
+    let objects = (0..OBJECTS as u64).map(Arc::new).collect::<Vec<_>>();
+
+    bench::<RandomState>("RandomState (SipHash)", &objects);
+    bench::<IdentityBuildHasher>("IdentityBuildHasher", &objects);
+}
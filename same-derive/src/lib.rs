@@ -0,0 +1,212 @@
+//! Derive macros for `Same` and `RefHash` traits from the `same` crate.
+//!
+//! Don't use this crate directly, enable the `derive` feature of `same`
+//! instead.
+//!
+//! The macros are intended for newtypes around shared pointers, such as
+//! `struct NodeRef(Rc<Node>)`. They forward the identity to a single field.
+//! If the struct has exactly one field, it's used automatically, otherwise
+//! the field has to be marked with `#[same(key)]`.
+//!
+//! # Example
+//!
+//! ```
+//! extern crate same;
+//!
+//! use same::{Same, RefHash, RefCmp};
+//! use std::rc::Rc;
+//! use std::sync::Arc;
+//!
+//! #[derive(Same, RefHash)]
+//! struct NodeRef(Rc<String>);
+//!
+//! #[derive(Same, RefHash)]
+//! struct Session {
+//!     #[same(key)]
+//!     inner: Arc<String>,
+//!     name: String,
+//! }
+//!
+//! # fn main() {
+//! let node = Rc::new("node".to_owned());
+//! assert!(NodeRef(node.clone()).same(&NodeRef(node)));
+//!
+//! let state = Arc::new("state".to_owned());
+//! let a = Session { inner: state.clone(), name: "a".to_owned() };
+//! let b = Session { inner: state, name: "b".to_owned() };
+//! assert!(RefCmp(a) == RefCmp(b));
+//! # }
+//! ```
+//!
+//! Fields of type `Box` and `&mut` are rejected, since they can never point
+//! to the same object as another value, so the identity would be meaningless:
+//!
+//! ```compile_fail
+//! extern crate same;
+//!
+//! #[derive(same::Same)]
+//! struct Unique(Box<u32>);
+//! # fn main() {}
+//! ```
+//!
+//! ```compile_fail
+//! extern crate same;
+//!
+//! #[derive(same::Same)]
+//! struct Unique<'a>(&'a mut u32);
+//! # fn main() {}
+//! ```
+//!
+//! Structs with more fields require an explicit key:
+//!
+//! ```compile_fail
+//! extern crate same;
+//!
+//! use std::rc::Rc;
+//!
+//! #[derive(same::Same)]
+//! struct Pair(Rc<u32>, Rc<u32>);
+//! # fn main() {}
+//! ```
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use syn::{Data, DeriveInput, Error, Field, Fields, Ident, Index, Member, Type};
+
+/// Derives `Same` by forwarding to a single field.
+///
+/// See the crate-level documentation for details.
+#[proc_macro_derive(Same, attributes(same))]
+pub fn derive_same(input: TokenStream) -> TokenStream {
+    expand(input, "Same", |member| quote! {
+        fn same(&self, other: &Self) -> bool {
+            ::same::Same::same(&self.#member, &other.#member)
+        }
+    })
+}
+
+/// Derives `RefHash` by forwarding to a single field.
+///
+/// See the crate-level documentation for details.
+#[proc_macro_derive(RefHash, attributes(same))]
+pub fn derive_ref_hash(input: TokenStream) -> TokenStream {
+    expand(input, "RefHash", |member| quote! {
+        fn ref_hash<H: ::same::__private::Hasher>(&self, hasher: &mut H) {
+            ::same::RefHash::ref_hash(&self.#member, hasher)
+        }
+    })
+}
+
+fn expand(input: TokenStream, trait_name: &str, method: fn(&Member) -> TokenStream2) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+
+    match derive(&input, trait_name, method) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => compile_error(error).into(),
+    }
+}
+
+/// Converts the error into `compile_error!` invocations.
+///
+/// `Error::to_compile_error` refers to `::core`, which is not available in
+/// crates using the 2015 edition.
+fn compile_error(error: Error) -> TokenStream2 {
+    error.into_iter().map(|error| {
+        let message = error.to_string();
+        quote_spanned!(error.span()=> compile_error!(#message);)
+    }).collect()
+}
+
+fn derive(input: &DeriveInput, trait_name: &str, method: fn(&Member) -> TokenStream2) -> Result<TokenStream2, Error> {
+    let trait_name = Ident::new(trait_name, Span::call_site());
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => {
+            let message = format!("`{}` can only be derived for structs", trait_name);
+            return Err(Error::new_spanned(&input.ident, message));
+        },
+    };
+    let (member, field) = key_field(fields, &input.ident)?;
+    check_type(&field.ty)?;
+
+    let ty = &field.ty;
+    let mut generics = input.generics.clone();
+    generics.make_where_clause().predicates.push(syn::parse_quote!(#ty: ::same::#trait_name));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let name = &input.ident;
+    let method = method(&member);
+
+    Ok(quote! {
+        impl #impl_generics ::same::#trait_name for #name #ty_generics #where_clause {
+            #method
+        }
+    })
+}
+
+/// Finds the field marked with `#[same(key)]` or the only field.
+fn key_field<'a>(fields: &'a Fields, name: &Ident) -> Result<(Member, &'a Field), Error> {
+    let mut key = None;
+    for (i, field) in fields.iter().enumerate() {
+        if !is_key(field)? {
+            continue;
+        }
+        if key.is_some() {
+            return Err(Error::new_spanned(field, "only one field can be marked with `#[same(key)]`"));
+        }
+        key = Some((i, field));
+    }
+
+    let (i, field) = match key {
+        Some(key) => key,
+        None if fields.len() == 1 => (0, fields.iter().next().expect("length checked above")),
+        None => {
+            let message = "the struct must have exactly one field or one field marked with `#[same(key)]`";
+            return Err(Error::new_spanned(name, message));
+        },
+    };
+
+    let member = match field.ident {
+        Some(ref ident) => Member::Named(ident.clone()),
+        None => Member::Unnamed(Index::from(i)),
+    };
+    Ok((member, field))
+}
+
+fn is_key(field: &Field) -> Result<bool, Error> {
+    let mut is_key = false;
+    for attr in &field.attrs {
+        if !attr.path().is_ident("same") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("key") {
+                is_key = true;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported `same` attribute, expected `key`"))
+            }
+        })?;
+    }
+    Ok(is_key)
+}
+
+/// Rejects types which can never be shared.
+fn check_type(ty: &Type) -> Result<(), Error> {
+    match *ty {
+        Type::Group(ref group) => check_type(&group.elem),
+        Type::Paren(ref paren) => check_type(&paren.elem),
+        Type::Reference(ref reference) if reference.mutability.is_some() => {
+            Err(Error::new_spanned(ty, "identity of `&mut` references is meaningless because they are never shared"))
+        },
+        Type::Path(ref path) if path.qself.is_none() && path.path.segments.last().is_some_and(|segment| segment.ident == "Box") => {
+            Err(Error::new_spanned(ty, "identity of `Box` is meaningless because it is never shared"))
+        },
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,280 @@
+//! Assertion macros testing identity.
+//!
+//! The helpers below pick what to print using autoref-based specialization:
+//! each query is implemented by several traits for differently referenced
+//! `Probe`s and method resolution picks the first one whose bounds hold. This
+//! only works for concrete types, so the queries are made by the macros.
+
+use core::fmt;
+
+/// Asserts that two expressions are the same object using `Same`.
+///
+/// On failure, the panic message contains the addresses of both objects and
+/// their values if they implement `Debug`. If they implement `PartialEq`, the
+/// message also says whether they are equal, since equal but distinct objects
+/// are a common cause of such failures.
+///
+/// Like `assert!`, this macro accepts a custom message as the third argument
+/// and further format arguments.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate same;
+///
+/// use std::rc::Rc;
+///
+/// # fn main() {
+/// let a = Rc::new(42);
+/// let b = a.clone();
+/// assert_same!(a, b);
+/// assert_not_same!(a, Rc::new(42), "cloning the value creates a new object");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! assert_same {
+    ($left:expr, $right:expr $(,)*) => {
+        $crate::__same_assert!(true, $left, $right, None)
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        $crate::__same_assert!(true, $left, $right, Some(format_args!($($arg)+)))
+    };
+}
+
+/// Asserts that two expressions are not the same object using `Same`.
+///
+/// On failure, the panic message contains the address of the object and its
+/// value if it implements `Debug`. See `assert_same!` for details.
+#[macro_export]
+macro_rules! assert_not_same {
+    ($left:expr, $right:expr $(,)*) => {
+        $crate::__same_assert!(false, $left, $right, None)
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        $crate::__same_assert!(false, $left, $right, Some(format_args!($($arg)+)))
+    };
+}
+
+/// Like `assert_same!`, but only enabled with debug assertions.
+#[macro_export]
+macro_rules! debug_assert_same {
+    ($($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::assert_same!($($arg)*);
+        }
+    };
+}
+
+/// Like `assert_not_same!`, but only enabled with debug assertions.
+#[macro_export]
+macro_rules! debug_assert_not_same {
+    ($($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::assert_not_same!($($arg)*);
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __same_assert {
+    ($expected:expr, $left:expr, $right:expr, $args:expr) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if $crate::Same::same(left, right) != $expected {
+                    #[allow(unused_imports)]
+                    use $crate::__private::{ViaDebug, ViaEq, ViaPointer, ViaWeak, Fallback};
+
+                    let (left_probe, right_probe) = ($crate::__private::Probe(left), $crate::__private::Probe(right));
+                    $crate::__private::assert_failed(
+                        $expected,
+                        $crate::__private::Details { address: (&&&left_probe).address(), value: (&&left_probe).debug() },
+                        $crate::__private::Details { address: (&&&right_probe).address(), value: (&&right_probe).debug() },
+                        (&&left_probe).equal(right),
+                        $args,
+                    );
+                }
+            }
+        }
+    };
+}
+
+/// Wrapper of an asserted value used to select the helper traits.
+pub struct Probe<'a, T: 'a>(pub &'a T);
+
+/// Address of an asserted value, if it can be determined.
+pub enum Address<'a> {
+    Pointer(&'a dyn fmt::Pointer),
+    Raw(*const ()),
+    Unknown,
+}
+
+impl<'a> fmt::Display for Address<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Address::Pointer(pointer) => fmt::Pointer::fmt(pointer, f),
+            Address::Raw(pointer) => fmt::Pointer::fmt(&pointer, f),
+            Address::Unknown => f.write_str("<unknown address>"),
+        }
+    }
+}
+
+/// What's known about an asserted value.
+pub struct Details<'a> {
+    pub address: Address<'a>,
+    pub value: Option<&'a dyn fmt::Debug>,
+}
+
+impl<'a> fmt::Display for Details<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value {
+            Some(value) => write!(f, "{} = {:?}", self.address, value),
+            None => write!(f, "{}", self.address),
+        }
+    }
+}
+
+/// Preferred for values implementing `fmt::Pointer`.
+pub trait ViaPointer<'a> {
+    fn address(&self) -> Address<'a>;
+}
+
+impl<'a, T: fmt::Pointer> ViaPointer<'a> for &&Probe<'a, T> {
+    fn address(&self) -> Address<'a> {
+        Address::Pointer(self.0)
+    }
+}
+
+/// Used for `Weak`, which doesn't implement `fmt::Pointer`.
+pub trait ViaWeak<'a> {
+    fn address(&self) -> Address<'a>;
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, T: ?Sized> ViaWeak<'a> for &Probe<'a, alloc::rc::Weak<T>> {
+    fn address(&self) -> Address<'a> {
+        Address::Raw(self.0.as_ptr() as *const ())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, T: ?Sized> ViaWeak<'a> for &Probe<'a, alloc::sync::Weak<T>> {
+    fn address(&self) -> Address<'a> {
+        Address::Raw(self.0.as_ptr() as *const ())
+    }
+}
+
+/// Used for values implementing `Debug`.
+pub trait ViaDebug<'a> {
+    fn debug(&self) -> Option<&'a dyn fmt::Debug>;
+}
+
+impl<'a, T: fmt::Debug> ViaDebug<'a> for &Probe<'a, T> {
+    fn debug(&self) -> Option<&'a dyn fmt::Debug> {
+        Some(self.0)
+    }
+}
+
+/// Used for values implementing `PartialEq`.
+pub trait ViaEq<T> {
+    fn equal(&self, other: &T) -> Option<bool>;
+}
+
+impl<'a, T: PartialEq> ViaEq<T> for &Probe<'a, T> {
+    fn equal(&self, other: &T) -> Option<bool> {
+        Some(*self.0 == *other)
+    }
+}
+
+/// Used when none of the more specific traits apply.
+pub trait Fallback<'a, T> {
+    fn address(&self) -> Address<'a> {
+        Address::Unknown
+    }
+
+    fn debug(&self) -> Option<&'a dyn fmt::Debug> {
+        None
+    }
+
+    fn equal(&self, _other: &T) -> Option<bool> {
+        None
+    }
+}
+
+impl<'a, T> Fallback<'a, T> for Probe<'a, T> {}
+
+/// Panics with the message of a failed assertion.
+#[cold]
+#[track_caller]
+pub fn assert_failed(expected: bool, left: Details, right: Details, equal: Option<bool>, args: Option<fmt::Arguments>) -> ! {
+    let (condition, note) = match (expected, equal) {
+        (true, Some(true)) => ("left.same(&right)", "\nnote: the values are equal, but they are distinct objects"),
+        (true, Some(false)) => ("left.same(&right)", "\nnote: the values are not equal either"),
+        (true, None) => ("left.same(&right)", ""),
+        (false, _) => ("!left.same(&right)", ""),
+    };
+    match args {
+        Some(args) => panic!("assertion `{}` failed: {}\n left: {}\nright: {}{}", condition, args, left, right, note),
+        None => panic!("assertion `{}` failed\n left: {}\nright: {}{}", condition, left, right, note),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+    use std::string::String;
+
+    fn panic_message<F: FnOnce()>(f: F) -> String {
+        let payload = panic::catch_unwind(panic::AssertUnwindSafe(f)).unwrap_err();
+        payload.downcast_ref::<String>().unwrap().clone()
+    }
+
+    #[test]
+    fn passing() {
+        let a = 1;
+        let b = 1;
+        assert_same!(&a, &a);
+        assert_not_same!(&a, &b, "{} and {} are distinct", a, b);
+        debug_assert_same!(&b, &b,);
+        debug_assert_not_same!(&a, &b);
+    }
+
+    #[test]
+    fn messages() {
+        let a = 1;
+        let b = 1;
+        let message = panic_message(|| assert_same!(&a, &b));
+        assert!(message.starts_with("assertion `left.same(&right)` failed\n left: 0x"));
+        assert!(message.contains(" = 1\nright: 0x"));
+        assert!(message.ends_with("equal, but they are distinct objects"));
+
+        let message = panic_message(|| assert_not_same!(&a, &a, "custom {}", 42));
+        assert!(message.starts_with("assertion `!left.same(&right)` failed: custom 42\n"));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn weak_and_composite() {
+        use std::rc::{Rc, Weak};
+
+        let a = Rc::new(1);
+        let weak = Rc::downgrade(&a);
+        let message = panic_message(|| assert_same!(weak, Weak::new()));
+        assert!(message.contains(" left: 0x"));
+
+        // Neither `Debug` nor `PartialEq`, and no address.
+        struct Opaque(Rc<i32>);
+        impl ::Same for Opaque {
+            fn same(&self, other: &Self) -> bool {
+                self.0.same(&other.0)
+            }
+        }
+        let message = panic_message(|| assert_same!(Opaque(a.clone()), Opaque(Rc::new(1))));
+        assert!(message.ends_with(" left: <unknown address>\nright: <unknown address>"));
+
+        let message = panic_message(|| assert_same!((a.clone(),), (Rc::new(2),)));
+        assert!(message.contains(" = (1,)\n"));
+        assert!(message.ends_with("not equal either"));
+    }
+}
@@ -0,0 +1,1148 @@
+//! Serialization of object graphs preserving sharing.
+//!
+//! Serializing an `Rc` by value duplicates objects that are shared and never
+//! terminates for graphs with cycles. The encoder in this module gives each
+//! distinct allocation an id (using `Same` and `RefHash` on its address), so
+//! every shared object is written only once and any later occurrence is
+//! written as a back-reference to it. The decoder then rebuilds a graph with
+//! exactly the same sharing, including cycles through `Weak` pointers.
+//!
+//! The format is a simple self-describing binary format: every value is
+//! prefixed by a tag describing its kind, so malformed or mismatched input
+//! is reported as an `Error` instead of being misinterpreted.
+//!
+//! Values are encoded by implementing `Encode` and `Decode`. They are
+//! implemented for primitive types, strings, common containers and shared
+//! pointers, so implementing them for a struct usually means encoding the
+//! fields one by one.
+//!
+//! # Weak pointers
+//!
+//! A `Weak` is encoded as a reference to its target, which can be defined
+//! before or after it. A `Weak` pointing to an object that is not reachable
+//! through strong pointers from the encoded value (it's kept alive by
+//! something outside the encoded graph) causes the object to be encoded after
+//! the value. After decoding, nothing outside the graph keeps such object
+//! alive, so it's dropped when the decoder is finished and the `Weak`
+//! dangles. `Weak` pointers that are already dangling are decoded as
+//! `Weak::new()`.
+//!
+//! Objects that `Weak`s point to are created using `Rc::new_cyclic` before
+//! the objects they strongly point to, so the `Weak`s in those objects can
+//! point to them. This supports any graph without cycles of strong pointers,
+//! such as trees with parent pointers, including nodes shared by several
+//! parents. To find these objects, the decoder reads the value twice: the
+//! first pass only collects the graph and checks the input, so decoding
+//! takes about twice as long as reading the value once. Malformed input is
+//! always reported by the first pass.
+//!
+//! # Example
+//!
+//! ```
+//! use same::Same;
+//! use same::codec::{self, Decode, Decoder, Encode, Encoder, Error};
+//! use std::cell::RefCell;
+//! use std::rc::{Rc, Weak};
+//!
+//! struct Node {
+//!     name: String,
+//!     parent: RefCell<Weak<Node>>,
+//!     children: RefCell<Vec<Rc<Node>>>,
+//! }
+//!
+//! impl Encode for Node {
+//!     fn encode(&self, encoder: &mut Encoder) {
+//!         self.name.encode(encoder);
+//!         self.parent.encode(encoder);
+//!         self.children.encode(encoder);
+//!     }
+//! }
+//!
+//! impl Decode for Node {
+//!     fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+//!         Ok(Node {
+//!             name: Decode::decode(decoder)?,
+//!             parent: Decode::decode(decoder)?,
+//!             children: Decode::decode(decoder)?,
+//!         })
+//!     }
+//! }
+//!
+//! let root = Rc::new(Node {
+//!     name: "root".to_owned(),
+//!     parent: RefCell::new(Weak::new()),
+//!     children: RefCell::new(Vec::new()),
+//! });
+//! let child = Rc::new(Node {
+//!     name: "child".to_owned(),
+//!     parent: RefCell::new(Rc::downgrade(&root)),
+//!     children: RefCell::new(Vec::new()),
+//! });
+//! // The child is shared.
+//! root.children.borrow_mut().extend(vec![child.clone(), child]);
+//!
+//! let bytes = codec::encode(&root);
+//! let decoded = codec::decode::<Rc<Node>>(&bytes).unwrap();
+//!
+//! let children = decoded.children.borrow();
+//! assert_eq!(children[0].name, "child");
+//! assert!(children[0].same(&children[1]));
+//! assert!(children[0].parent.borrow().upgrade().unwrap().same(&decoded));
+//! ```
+
+use core::any::Any;
+use core::cell::RefCell;
+use core::fmt;
+use std::boxed::Box;
+use core::mem;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::{self, Rc};
+use std::string::String;
+use std::sync::{self, Arc};
+use std::vec::Vec;
+
+use shared::Shared;
+use RefCmp;
+
+/// Bytes at the start of the encoded data, including the format version.
+const HEADER: &[u8] = b"same\x01";
+
+mod tag {
+    pub const NULL: u8 = 0;
+    pub const FALSE: u8 = 1;
+    pub const TRUE: u8 = 2;
+    pub const UNSIGNED: u8 = 3;
+    pub const SIGNED: u8 = 4;
+    pub const FLOAT: u8 = 5;
+    pub const STR: u8 = 6;
+    pub const SEQ: u8 = 7;
+    pub const SOME: u8 = 8;
+    /// Definition of a shared object, followed by its id and value.
+    pub const SHARED: u8 = 9;
+    /// Weak reference to a shared object defined before or after it,
+    /// followed by its id.
+    pub const WEAK: u8 = 10;
+    /// Reference to a previously defined shared object, followed by its id.
+    pub const REF: u8 = 11;
+}
+
+/// Error returned when decoding fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The input doesn't start with the expected header.
+    InvalidHeader,
+    /// The input ended in the middle of a value.
+    UnexpectedEnd,
+    /// The input contains a value of a different kind than expected.
+    UnexpectedTag(u8),
+    /// A number doesn't fit into the expected type.
+    OutOfRange,
+    /// A string is not valid UTF-8.
+    InvalidUtf8,
+    /// The input contains data after the end of the value.
+    TrailingBytes,
+    /// A back-reference refers to an object that wasn't defined.
+    UnknownId(u64),
+    /// An object with the same id was already defined.
+    DuplicateId(u64),
+    /// A back-reference refers to an object of a different type.
+    TypeMismatch(u64),
+    /// A strong pointer refers to an object that is still being decoded.
+    ///
+    /// This happens when the encoded objects form a cycle of strong pointers.
+    InProgress(u64),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidHeader => f.write_str("invalid header"),
+            Error::UnexpectedEnd => f.write_str("unexpected end of input"),
+            Error::UnexpectedTag(tag) => write!(f, "unexpected tag {}", tag),
+            Error::OutOfRange => f.write_str("number out of range"),
+            Error::InvalidUtf8 => f.write_str("invalid UTF-8 in string"),
+            Error::TrailingBytes => f.write_str("trailing bytes after the value"),
+            Error::UnknownId(id) => write!(f, "reference to unknown object {}", id),
+            Error::DuplicateId(id) => write!(f, "object {} defined twice", id),
+            Error::TypeMismatch(id) => write!(f, "object {} has a different type", id),
+            Error::InProgress(id) => write!(f, "object {} is referred to while it's being decoded", id),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Encodes the value into a new buffer.
+pub fn encode<T: Encode + ?Sized>(value: &T) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+    encoder.encode(value);
+    encoder.into_bytes()
+}
+
+/// Decodes a value encoded by `encode`.
+///
+/// Fails if the data doesn't contain exactly one value of type `T`.
+pub fn decode<T: Decode>(bytes: &[u8]) -> Result<T, Error> {
+    let mut decoder = Decoder::new(bytes)?;
+    let value = decoder.decode()?;
+    decoder.finish()?;
+    Ok(value)
+}
+
+/// Values that can be encoded.
+pub trait Encode {
+    /// Writes the value into the encoder.
+    fn encode(&self, encoder: &mut Encoder);
+}
+
+/// Values that can be decoded.
+///
+/// Values are read twice (see the module documentation), so implementations
+/// must read the same data each time.
+pub trait Decode: Sized {
+    /// Reads the value from the decoder.
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error>;
+}
+
+/// Closure writing the definition of an object referred to by a `Weak`.
+type Define = Box<dyn FnOnce(&mut Encoder)>;
+
+/// Writes values into a buffer.
+pub struct Encoder {
+    bytes: Vec<u8>,
+    /// Ids of shared objects by their addresses and whether the objects were
+    /// already defined.
+    ids: HashMap<RefCmp<*const ()>, (u64, bool)>,
+    /// Whether a value is being encoded using `encode`.
+    encoding: bool,
+    /// Objects referred to by `Weak`s before being defined.
+    deferred: VecDeque<Define>,
+}
+
+impl Encoder {
+    /// Creates an encoder with an empty buffer.
+    pub fn new() -> Self {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(HEADER);
+        Encoder { bytes, ids: HashMap::new(), encoding: false, deferred: VecDeque::new() }
+    }
+
+    /// Encodes the value, followed by objects its `Weak`s point to which
+    /// are not reachable through strong pointers.
+    ///
+    /// Objects that were already encoded by this encoder are encoded as
+    /// back-references. Calling `value.encode(encoder)` directly works as
+    /// well, but then each shared pointer in the value is encoded as a
+    /// separate value and has to be decoded as such.
+    pub fn encode<T: Encode + ?Sized>(&mut self, value: &T) {
+        if self.encoding {
+            return value.encode(self);
+        }
+        self.encoding = true;
+        value.encode(self);
+        while let Some(define) = self.deferred.pop_front() {
+            define(self);
+        }
+        self.encoding = false;
+    }
+
+    /// Returns the encoded data.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_unsigned(&mut self, value: u64) {
+        self.bytes.push(tag::UNSIGNED);
+        self.write_u64(value);
+    }
+
+    fn write_signed(&mut self, value: i64) {
+        self.bytes.push(tag::SIGNED);
+        self.write_u64(value as u64);
+    }
+
+    fn write_float(&mut self, value: f64) {
+        self.bytes.push(tag::FLOAT);
+        self.write_u64(value.to_bits());
+    }
+
+    /// Writes the header of a sequence of `len` values.
+    ///
+    /// The values have to be encoded right after this call.
+    pub fn write_seq(&mut self, len: usize) {
+        self.bytes.push(tag::SEQ);
+        self.write_u64(len as u64);
+    }
+
+    fn write_shared<P: Shared + Encode>(&mut self, ptr: &P) where P::Target: Encode {
+        if !self.encoding {
+            return self.encode(ptr);
+        }
+
+        let key = RefCmp(ptr.target() as *const P::Target as *const ());
+        let id = match self.ids.get(&key) {
+            Some(&(id, true)) => {
+                self.bytes.push(tag::REF);
+                return self.write_u64(id);
+            },
+            Some(&(id, false)) => id,
+            None => self.ids.len() as u64,
+        };
+        self.ids.insert(key, (id, true));
+        self.bytes.push(tag::SHARED);
+        self.write_u64(id);
+        ptr.target().encode(self);
+    }
+
+    fn write_weak<P: Shared + Encode>(&mut self, weak: &P::Weak) where P::Target: Encode, P::Weak: Encode {
+        if !self.encoding {
+            return self.encode(weak);
+        }
+
+        let ptr = match P::upgrade(weak) {
+            Some(ptr) => ptr,
+            None => return self.bytes.push(tag::NULL),
+        };
+        let key = RefCmp(ptr.target() as *const P::Target as *const ());
+        let id = match self.ids.get(&key) {
+            Some(&(id, _)) => id,
+            None => {
+                let id = self.ids.len() as u64;
+                self.ids.insert(key, (id, false));
+                // Defined after the value unless it's reached through a
+                // strong pointer before that.
+                self.deferred.push_back(Box::new(move |encoder: &mut Encoder| {
+                    if !encoder.ids[&key].1 {
+                        encoder.write_shared(&ptr);
+                    }
+                }));
+                id
+            },
+        };
+        self.bytes.push(tag::WEAK);
+        self.write_u64(id);
+    }
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Marker of an object being decoded which can't be referred to yet.
+struct Pending;
+
+/// Pass of `Decoder::decode`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pass {
+    /// No value is being decoded using `decode`.
+    None,
+    /// The graph of the value is being collected.
+    Collect,
+    /// The value is being decoded for real.
+    Decode,
+}
+
+/// Function reading an object referred to by a `Weak` with the type of the
+/// `Weak`.
+type ReadTarget<'a> = fn(&mut Decoder<'a>, u64) -> Result<(), Error>;
+
+/// Reads values from a buffer.
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// Decoded shared objects by their ids. Objects which are being decoded
+    /// are represented by `Pending` or by a weak pointer if `Weak`s point to
+    /// them.
+    objects: HashMap<u64, Box<dyn Any>>,
+    pass: Pass,
+    /// Objects being decoded.
+    stack: Vec<u64>,
+    /// Positions of values of object definitions and of their ends.
+    definitions: HashMap<u64, (usize, usize)>,
+    /// Objects strongly pointing to each object.
+    parents: HashMap<u64, Vec<u64>>,
+    /// Objects `Weak`s point to.
+    weak_targets: HashMap<u64, ReadTarget<'a>>,
+    /// Objects `Weak`s point to which weren't defined yet.
+    undefined: HashSet<u64>,
+    /// Types of `Weak`s to check once all objects are defined.
+    checks: Vec<(u64, ReadTarget<'a>)>,
+    /// Objects whose ancestors in `weak_targets` were already decoded or are
+    /// being decoded.
+    ready: HashSet<u64>,
+}
+
+impl<'a> Decoder<'a> {
+    /// Creates a decoder reading from the data created by `Encoder`.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        if !bytes.starts_with(HEADER) {
+            return Err(Error::InvalidHeader);
+        }
+        Ok(Decoder {
+            bytes: &bytes[HEADER.len()..],
+            position: 0,
+            objects: HashMap::new(),
+            pass: Pass::None,
+            stack: Vec::new(),
+            definitions: HashMap::new(),
+            parents: HashMap::new(),
+            weak_targets: HashMap::new(),
+            undefined: HashSet::new(),
+            checks: Vec::new(),
+            ready: HashSet::new(),
+        })
+    }
+
+    /// Decodes a value written by `Encoder::encode`, together with the
+    /// objects its `Weak`s point to.
+    ///
+    /// Objects decoded before by this decoder can be referred to by the
+    /// value. Calling `T::decode` directly works as well, but only if each
+    /// shared pointer in the value was encoded as a separate value.
+    pub fn decode<T: Decode>(&mut self) -> Result<T, Error> {
+        if self.pass != Pass::None {
+            return T::decode(self);
+        }
+
+        let start = self.position;
+        self.pass = Pass::Collect;
+        let result = self.collect::<T>();
+        let end = self.position;
+        for id in self.definitions.keys() {
+            self.objects.remove(id);
+        }
+
+        let result = result.and_then(|()| {
+            self.position = start;
+            self.pass = Pass::Decode;
+            T::decode(self)
+        });
+        self.position = end;
+        self.pass = Pass::None;
+        self.stack.clear();
+        self.definitions.clear();
+        self.parents.clear();
+        self.weak_targets.clear();
+        self.undefined.clear();
+        self.checks.clear();
+        self.ready.clear();
+        result
+    }
+
+    /// Checks that all data was consumed.
+    ///
+    /// Shared objects that are only referred to by weak pointers are
+    /// dropped at this point.
+    pub fn finish(self) -> Result<(), Error> {
+        if self.position == self.bytes.len() {
+            Ok(())
+        } else {
+            Err(Error::TrailingBytes)
+        }
+    }
+
+    /// Reads the value and the objects defined after it, only collecting
+    /// the graph.
+    fn collect<T: Decode>(&mut self) -> Result<(), Error> {
+        T::decode(self)?;
+        while !self.undefined.is_empty() {
+            self.expect_tag(tag::SHARED)?;
+            let id = self.read_u64()?;
+            if !self.undefined.contains(&id) {
+                return Err(if self.objects.contains_key(&id) { Error::DuplicateId(id) } else { Error::UnknownId(id) });
+            }
+            let read = self.weak_targets[&id];
+            read(self, id)?;
+        }
+        for (id, check) in mem::take(&mut self.checks) {
+            check(self, id)?;
+        }
+        Ok(())
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.remaining() < len {
+            return Err(Error::UnexpectedEnd);
+        }
+        let bytes = &self.bytes[self.position..(self.position + len)];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn peek_tag(&self) -> Result<u8, Error> {
+        self.bytes.get(self.position).cloned().ok_or(Error::UnexpectedEnd)
+    }
+
+    fn read_tag(&mut self) -> Result<u8, Error> {
+        let tag = self.peek_tag()?;
+        self.position += 1;
+        Ok(tag)
+    }
+
+    fn expect_tag(&mut self, expected: u8) -> Result<(), Error> {
+        match self.read_tag()? {
+            tag if tag == expected => Ok(()),
+            tag => Err(Error::UnexpectedTag(tag)),
+        }
+    }
+
+    fn read_u64(&mut self) -> Result<u64, Error> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn read_len(&mut self) -> Result<usize, Error> {
+        let len = self.read_u64()?;
+        if len > usize::MAX as u64 {
+            return Err(Error::OutOfRange);
+        }
+        Ok(len as usize)
+    }
+
+    fn read_unsigned(&mut self) -> Result<u64, Error> {
+        self.expect_tag(tag::UNSIGNED)?;
+        self.read_u64()
+    }
+
+    fn read_signed(&mut self) -> Result<i64, Error> {
+        self.expect_tag(tag::SIGNED)?;
+        Ok(self.read_u64()? as i64)
+    }
+
+    fn read_float(&mut self) -> Result<f64, Error> {
+        self.expect_tag(tag::FLOAT)?;
+        Ok(f64::from_bits(self.read_u64()?))
+    }
+
+    /// Reads the header of a sequence and returns its length.
+    pub fn read_seq(&mut self) -> Result<usize, Error> {
+        self.expect_tag(tag::SEQ)?;
+        self.read_len()
+    }
+
+    fn read_shared<P: Shared + Decode>(&mut self) -> Result<P, Error> where P::Target: Decode {
+        if self.pass == Pass::None {
+            return self.decode();
+        }
+
+        let tag = self.read_tag()?;
+        let id = self.read_u64()?;
+        if tag != tag::REF && tag != tag::SHARED {
+            return Err(Error::UnexpectedTag(tag));
+        }
+        if self.pass == Pass::Decode {
+            let ptr = self.object::<P>(id)?;
+            if tag == tag::SHARED {
+                self.position = self.definitions[&id].1;
+            }
+            return Ok(ptr);
+        }
+
+        if let Some(&parent) = self.stack.last() {
+            self.parents.entry(id).or_default().push(parent);
+        }
+        if tag == tag::SHARED {
+            return self.read_definition(id);
+        }
+        let object = self.objects.get(&id).ok_or(Error::UnknownId(id))?;
+        match object.downcast_ref::<P>() {
+            Some(ptr) => Ok(ptr.clone()),
+            None if object.is::<Pending>() => Err(Error::InProgress(id)),
+            None => Err(Error::TypeMismatch(id)),
+        }
+    }
+
+    fn read_weak<P: Shared + Decode>(&mut self) -> Result<P::Weak, Error> where P::Target: Decode, P::Weak: Decode {
+        if self.pass == Pass::None {
+            return self.decode();
+        }
+
+        match self.read_tag()? {
+            tag::NULL => return Ok(P::dangling()),
+            tag::WEAK => (),
+            tag => return Err(Error::UnexpectedTag(tag)),
+        }
+        let id = self.read_u64()?;
+        if self.pass == Pass::Decode {
+            if let Some(object) = self.objects.get(&id) {
+                if let Some(ptr) = object.downcast_ref::<P>() {
+                    return Ok(ptr.downgrade());
+                }
+                return object.downcast_ref::<P::Weak>().cloned().ok_or(Error::TypeMismatch(id));
+            }
+            return self.object::<P>(id).map(|ptr| ptr.downgrade());
+        }
+
+        self.weak_targets.entry(id).or_insert(Self::read_target::<P>);
+        match self.objects.get(&id) {
+            Some(object) if object.is::<P>() => (),
+            Some(object) if !object.is::<Pending>() => return Err(Error::TypeMismatch(id)),
+            Some(_) => self.checks.push((id, Self::check_target::<P>)),
+            None => {
+                self.undefined.insert(id);
+                self.checks.push((id, Self::check_target::<P>));
+            },
+        }
+        // Nothing can be read through the `Weak` in this pass.
+        Ok(P::dangling())
+    }
+
+    /// Reads the definition of an object after its id.
+    fn read_definition<P: Shared>(&mut self, id: u64) -> Result<P, Error> where P::Target: Decode {
+        let start = self.position;
+        if self.pass == Pass::Collect {
+            if self.objects.contains_key(&id) {
+                return Err(Error::DuplicateId(id));
+            }
+            self.undefined.remove(&id);
+        }
+
+        self.stack.push(id);
+        let ptr = if self.pass == Pass::Decode && self.weak_targets.contains_key(&id) {
+            Ok(P::new_cyclic(|weak| {
+                self.objects.insert(id, Box::new(weak.clone()));
+                match P::Target::decode(self) {
+                    Ok(value) => value,
+                    // The first pass read the same data successfully.
+                    Err(error) => panic!("decoding object {} failed on the second pass: {}", id, error),
+                }
+            }))
+        } else {
+            self.objects.insert(id, Box::new(Pending));
+            P::Target::decode(self).map(P::new)
+        };
+        self.stack.pop();
+
+        let ptr = ptr?;
+        self.objects.insert(id, Box::new(ptr.clone()));
+        if self.pass == Pass::Collect {
+            self.definitions.insert(id, (start, self.position));
+        }
+        Ok(ptr)
+    }
+
+    /// Returns the object, reading its definition first if it wasn't read
+    /// yet in this pass.
+    fn object<P: Shared>(&mut self, id: u64) -> Result<P, Error> where P::Target: Decode {
+        if !self.objects.contains_key(&id) {
+            self.read_weak_targets(id)?;
+        }
+        if let Some(object) = self.objects.get(&id) {
+            return match object.downcast_ref::<P>() {
+                Some(ptr) => Ok(ptr.clone()),
+                None if object.is::<Pending>() || object.is::<P::Weak>() => Err(Error::InProgress(id)),
+                None => Err(Error::TypeMismatch(id)),
+            };
+        }
+
+        let &(start, _) = self.definitions.get(&id).ok_or(Error::UnknownId(id))?;
+        let position = mem::replace(&mut self.position, start);
+        let ptr = self.read_definition(id);
+        self.position = position;
+        ptr
+    }
+
+    /// Reads objects `Weak`s point to which strongly point to the object,
+    /// directly or indirectly, so that they are being read when the `Weak`s
+    /// pointing to them are reached from the object.
+    ///
+    /// Each object is walked through at most once until it's read or ready,
+    /// so this takes linear time in total.
+    fn read_weak_targets(&mut self, id: u64) -> Result<(), Error> {
+        let mut index = 0;
+        while let Some(&parent) = self.parents.get(&id).and_then(|parents| parents.get(index)) {
+            index += 1;
+            if self.objects.contains_key(&parent) || self.ready.contains(&parent) {
+                continue;
+            }
+            self.read_weak_targets(parent)?;
+            match self.weak_targets.get(&parent).cloned() {
+                Some(read) => read(self, parent)?,
+                None => {
+                    self.ready.insert(parent);
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn read_target<P: Shared>(&mut self, id: u64) -> Result<(), Error> where P::Target: Decode {
+        match self.pass {
+            Pass::Decode => self.object::<P>(id).map(drop),
+            _ => self.read_definition::<P>(id).map(drop),
+        }
+    }
+
+    fn check_target<P: Shared>(&mut self, id: u64) -> Result<(), Error> {
+        match self.objects.get(&id) {
+            Some(object) if object.is::<P>() => Ok(()),
+            Some(_) => Err(Error::TypeMismatch(id)),
+            None => Err(Error::UnknownId(id)),
+        }
+    }
+}
+
+macro_rules! unsigned_impls {
+    ($($ty:ty),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, encoder: &mut Encoder) {
+                    encoder.write_unsigned(*self as u64);
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+                    let value = decoder.read_unsigned()?;
+                    if value > <$ty>::MAX as u64 {
+                        return Err(Error::OutOfRange);
+                    }
+                    Ok(value as $ty)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! signed_impls {
+    ($($ty:ty),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, encoder: &mut Encoder) {
+                    encoder.write_signed(*self as i64);
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+                    let value = decoder.read_signed()?;
+                    if value < <$ty>::MIN as i64 || value > <$ty>::MAX as i64 {
+                        return Err(Error::OutOfRange);
+                    }
+                    Ok(value as $ty)
+                }
+            }
+        )*
+    };
+}
+
+unsigned_impls!(u8, u16, u32, u64, usize);
+signed_impls!(i8, i16, i32, i64, isize);
+
+impl Encode for f64 {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.write_float(*self);
+    }
+}
+
+impl Decode for f64 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        decoder.read_float()
+    }
+}
+
+impl Encode for f32 {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.write_float(f64::from(*self));
+    }
+}
+
+impl Decode for f32 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        decoder.read_float().map(|value| value as f32)
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.bytes.push(if *self { tag::TRUE } else { tag::FALSE });
+    }
+}
+
+impl Decode for bool {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        match decoder.read_tag()? {
+            tag::FALSE => Ok(false),
+            tag::TRUE => Ok(true),
+            tag => Err(Error::UnexpectedTag(tag)),
+        }
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.bytes.push(tag::STR);
+        encoder.write_u64(self.len() as u64);
+        encoder.bytes.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, encoder: &mut Encoder) {
+        self.as_str().encode(encoder);
+    }
+}
+
+impl Decode for String {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        decoder.expect_tag(tag::STR)?;
+        let len = decoder.read_len()?;
+        let bytes = decoder.read_bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| Error::InvalidUtf8)
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.write_seq(self.len());
+        for item in self {
+            item.encode(encoder);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, encoder: &mut Encoder) {
+        self[..].encode(encoder);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        let len = decoder.read_seq()?;
+        // Don't trust the length too much, each item takes at least a byte.
+        let mut items = Vec::with_capacity(len.min(decoder.remaining()));
+        for _ in 0..len {
+            items.push(T::decode(decoder)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, encoder: &mut Encoder) {
+        match *self {
+            Some(ref value) => {
+                encoder.bytes.push(tag::SOME);
+                value.encode(encoder);
+            },
+            None => encoder.bytes.push(tag::NULL),
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        match decoder.read_tag()? {
+            tag::SOME => T::decode(decoder).map(Some),
+            tag::NULL => Ok(None),
+            tag => Err(Error::UnexpectedTag(tag)),
+        }
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, encoder: &mut Encoder) {
+        (**self).encode(encoder);
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for Box<T> {
+    fn encode(&self, encoder: &mut Encoder) {
+        (**self).encode(encoder);
+    }
+}
+
+impl<T: Decode> Decode for Box<T> {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        T::decode(decoder).map(Box::new)
+    }
+}
+
+/// Panics if the value is mutably borrowed.
+impl<T: Encode + ?Sized> Encode for RefCell<T> {
+    fn encode(&self, encoder: &mut Encoder) {
+        self.borrow().encode(encoder);
+    }
+}
+
+impl<T: Decode> Decode for RefCell<T> {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        T::decode(decoder).map(RefCell::new)
+    }
+}
+
+macro_rules! tuple_impls {
+    ($len:expr, $($name:ident $idx:tt),+) => {
+        impl<$($name: Encode),+> Encode for ($($name,)+) {
+            fn encode(&self, encoder: &mut Encoder) {
+                encoder.write_seq($len);
+                $(self.$idx.encode(encoder);)+
+            }
+        }
+
+        impl<$($name: Decode),+> Decode for ($($name,)+) {
+            fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+                match decoder.read_seq()? {
+                    $len => Ok(($($name::decode(decoder)?,)+)),
+                    _ => Err(Error::OutOfRange),
+                }
+            }
+        }
+    };
+}
+
+tuple_impls!(1, A 0);
+tuple_impls!(2, A 0, B 1);
+tuple_impls!(3, A 0, B 1, C 2);
+tuple_impls!(4, A 0, B 1, C 2, D 3);
+
+/// Encodes the object only once, later occurrences are encoded as
+/// back-references.
+impl<T: Encode + 'static> Encode for Rc<T> {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.write_shared(self);
+    }
+}
+
+impl<T: Decode + 'static> Decode for Rc<T> {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        decoder.read_shared()
+    }
+}
+
+/// Encodes the object only once, later occurrences are encoded as
+/// back-references.
+impl<T: Encode + 'static> Encode for Arc<T> {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.write_shared(self);
+    }
+}
+
+impl<T: Decode + 'static> Decode for Arc<T> {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        decoder.read_shared()
+    }
+}
+
+impl<T: Encode + 'static> Encode for rc::Weak<T> {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.write_weak::<Rc<T>>(self);
+    }
+}
+
+impl<T: Decode + 'static> Decode for rc::Weak<T> {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        decoder.read_weak::<Rc<T>>()
+    }
+}
+
+impl<T: Encode + 'static> Encode for sync::Weak<T> {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.write_weak::<Arc<T>>(self);
+    }
+}
+
+impl<T: Decode + 'static> Decode for sync::Weak<T> {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        decoder.read_weak::<Arc<T>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, Decode, Decoder, Encode, Encoder, Error};
+    use std::borrow::ToOwned;
+    use std::cell::RefCell;
+    use std::rc::{Rc, Weak};
+    use std::string::String;
+    use std::sync::Arc;
+    use std::vec::Vec;
+    use Same;
+
+    struct Node {
+        name: String,
+        parent: RefCell<Weak<Node>>,
+        children: RefCell<Vec<Rc<Node>>>,
+    }
+
+    impl Node {
+        fn new(name: &str) -> Rc<Self> {
+            Rc::new(Node { name: name.to_owned(), parent: RefCell::new(Weak::new()), children: RefCell::new(Vec::new()) })
+        }
+
+        fn add(self: &Rc<Self>, child: &Rc<Node>) {
+            *child.parent.borrow_mut() = Rc::downgrade(self);
+            self.children.borrow_mut().push(child.clone());
+        }
+
+        fn child(&self, index: usize) -> Rc<Node> {
+            self.children.borrow()[index].clone()
+        }
+    }
+
+    impl Encode for Node {
+        fn encode(&self, encoder: &mut Encoder) {
+            self.name.encode(encoder);
+            self.parent.encode(encoder);
+            self.children.encode(encoder);
+        }
+    }
+
+    impl Decode for Node {
+        fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+            Ok(Node {
+                name: Decode::decode(decoder)?,
+                parent: Decode::decode(decoder)?,
+                children: Decode::decode(decoder)?,
+            })
+        }
+    }
+
+    #[test]
+    fn values() {
+        let value = (42u8, -7i64, Some("text".to_owned()), [1.5f64, 2.0].to_vec());
+        let decoded = decode::<(u8, i64, Option<String>, Vec<f64>)>(&encode(&value)).unwrap();
+        assert!(decoded == value);
+
+        assert_eq!(decode::<u8>(&encode(&300u32)), Err(Error::OutOfRange));
+        assert_eq!(decode::<bool>(&encode(&1u8)), Err(Error::UnexpectedTag(3)));
+        let bytes = encode(&"text");
+        assert_eq!(decode::<String>(&bytes[..bytes.len() - 1]), Err(Error::UnexpectedEnd));
+        assert_eq!(decode::<String>(&bytes[1..]), Err(Error::InvalidHeader));
+    }
+
+    #[test]
+    fn sharing() {
+        let shared = Arc::new(42u32);
+        let other = Arc::new(42u32);
+        let value = [shared.clone(), other, shared].to_vec();
+
+        let decoded = decode::<Vec<Arc<u32>>>(&encode(&value)).unwrap();
+        assert!(decoded[0].same(&decoded[2]));
+        assert!(!decoded[0].same(&decoded[1]));
+        assert_eq!(*decoded[1], 42);
+    }
+
+    #[test]
+    fn cycles() {
+        // root -> {a, b}, a -> c, b -> c, with parent pointers.
+        let root = Node::new("root");
+        let a = Node::new("a");
+        let b = Node::new("b");
+        let c = Node::new("c");
+        root.add(&a);
+        root.add(&b);
+        b.add(&c);
+        a.add(&c);
+
+        let decoded = decode::<Rc<Node>>(&encode(&root)).unwrap();
+        let (a, b) = (decoded.child(0), decoded.child(1));
+        assert_eq!((&*a.name, &*b.name), ("a", "b"));
+        assert!(a.child(0).same(&b.child(0)));
+        assert!(a.parent.borrow().upgrade().unwrap().same(&decoded));
+        assert!(decoded.parent.borrow().upgrade().is_none());
+        assert!(b.child(0).parent.borrow().upgrade().unwrap().same(&a));
+        assert_eq!(Rc::strong_count(&decoded), 1);
+    }
+
+    #[test]
+    fn weak_before_strong() {
+        // The parent of the shared node is encoded after the node.
+        let root = Node::new("root");
+        let (a, b, c) = (Node::new("a"), Node::new("b"), Node::new("c"));
+        root.add(&a);
+        root.add(&b);
+        a.add(&c);
+        b.add(&c);
+
+        let decoded = decode::<Rc<Node>>(&encode(&root)).unwrap();
+        let (a, b) = (decoded.child(0), decoded.child(1));
+        assert!(a.child(0).same(&b.child(0)));
+        assert!(a.child(0).parent.borrow().upgrade().unwrap().same(&b));
+        assert!(b.parent.borrow().upgrade().unwrap().same(&decoded));
+        assert_eq!(Rc::strong_count(&a.child(0)), 3);
+    }
+
+    #[test]
+    fn interleaved_weak_before_strong() {
+        // Shared node i is a child of parents i and i + 1 and points to the
+        // latter, which is encoded after it.
+        let root = Node::new("root");
+        let parents: Vec<_> = (0..5).map(|i: u32| Node::new(&std::format!("{}", i))).collect();
+        for parent in &parents {
+            root.add(parent);
+        }
+        for i in 0..4 {
+            let shared = Node::new(&std::format!("shared {}", i));
+            parents[i].add(&shared);
+            parents[i + 1].add(&shared);
+        }
+
+        let decoded = decode::<Rc<Node>>(&encode(&root)).unwrap();
+        for i in 0..4 {
+            let shared = decoded.child(i).child(i.min(1));
+            assert_eq!(shared.name, std::format!("shared {}", i));
+            assert!(shared.same(&decoded.child(i + 1).child(0)));
+            assert!(shared.parent.borrow().upgrade().unwrap().same(&decoded.child(i + 1)));
+        }
+    }
+
+    #[test]
+    fn weaks() {
+        let alive = Node::new("alive");
+        let dead = Rc::downgrade(&Node::new("dead"));
+        let value = (Rc::downgrade(&alive), dead, Rc::downgrade(&alive));
+
+        let bytes = encode(&value);
+        let mut decoder = Decoder::new(&bytes).unwrap();
+        let (first, dead, second): (Weak<Node>, Weak<Node>, Weak<Node>) = decoder.decode().unwrap();
+        assert!(first.same(&second));
+        assert_eq!(first.upgrade().unwrap().name, "alive");
+        assert!(dead.upgrade().is_none());
+
+        // Nothing keeps the object alive after decoding is finished.
+        decoder.finish().unwrap();
+        assert!(first.upgrade().is_none());
+    }
+
+    #[test]
+    fn invalid_references() {
+        let shared = Rc::new(1u32);
+        let bytes = encode(&(shared.clone(), shared));
+        assert_eq!(decode::<(Rc<u32>, Rc<i32>)>(&bytes), Err(Error::TypeMismatch(0)));
+
+        // A back-reference without a definition.
+        let mut bytes = super::HEADER.to_vec();
+        bytes.push(super::tag::REF);
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        assert_eq!(decode::<Rc<u32>>(&bytes), Err(Error::UnknownId(0)));
+
+        // A weak reference to an object which is never defined.
+        let mut bytes = super::HEADER.to_vec();
+        bytes.push(super::tag::WEAK);
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        assert_eq!(decode::<Weak<u32>>(&bytes).err(), Some(Error::UnexpectedEnd));
+        bytes.push(super::tag::SHARED);
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        assert_eq!(decode::<Weak<u32>>(&bytes).err(), Some(Error::UnknownId(1)));
+
+        // Errors inside cyclic objects are reported as well.
+        let root = Node::new("root");
+        root.add(&Node::new("child"));
+        let mut bytes = encode(&root);
+        bytes.truncate(bytes.len() - 1);
+        assert_eq!(decode::<Rc<Node>>(&bytes).err(), Some(Error::UnexpectedEnd));
+    }
+}
@@ -0,0 +1,253 @@
+//! Implementations for tuples, arrays, slices, `Vec` and `Option`.
+//!
+//! Composite values are the same if all their parts are the same. Sequences
+//! are compared element-wise and they must have the same length, `None` is
+//! the same as `None`. Hashing and ordering is consistent with this: the
+//! parts are hashed (sequences also hash their length) and compared
+//! lexicographically.
+//!
+//! Note that `&[T]` is still compared by address, like any other reference.
+//! Element-wise comparison applies to `[T]` itself, arrays and `Vec<T>`, so
+//! compare `*a` with `*b` or use `RefCmp<Vec<T>>` if that's desired.
+
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+
+use {AddressOf, FmtAddress, RefHash, RefOrd, Same};
+
+impl<T: Same> Same for [T] {
+    fn same(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a.same(b))
+    }
+}
+
+impl<T: RefHash> RefHash for [T] {
+    fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+        self.len().hash(hasher);
+        for item in self {
+            item.ref_hash(hasher);
+        }
+    }
+}
+
+impl<T: RefOrd> RefOrd for [T] {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        for (a, b) in self.iter().zip(other) {
+            match a.ref_cmp(b) {
+                Ordering::Equal => (),
+                ordering => return ordering,
+            }
+        }
+        self.len().cmp(&other.len())
+    }
+}
+
+impl<T: FmtAddress> FmtAddress for [T] {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter().map(AddressOf)).finish()
+    }
+}
+
+impl<T: Same, const N: usize> Same for [T; N] {
+    fn same(&self, other: &Self) -> bool {
+        self[..].same(&other[..])
+    }
+}
+
+/// Hashes the same way as `[T]`.
+impl<T: RefHash, const N: usize> RefHash for [T; N] {
+    fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+        self[..].ref_hash(hasher);
+    }
+}
+
+impl<T: RefOrd, const N: usize> RefOrd for [T; N] {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        self[..].ref_cmp(&other[..])
+    }
+}
+
+impl<T: FmtAddress, const N: usize> FmtAddress for [T; N] {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self[..].fmt_address(f)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Same> Same for alloc::vec::Vec<T> {
+    fn same(&self, other: &Self) -> bool {
+        self[..].same(&other[..])
+    }
+}
+
+/// Hashes the same way as `[T]`.
+#[cfg(feature = "alloc")]
+impl<T: RefHash> RefHash for alloc::vec::Vec<T> {
+    fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+        self[..].ref_hash(hasher);
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: RefOrd> RefOrd for alloc::vec::Vec<T> {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        self[..].ref_cmp(&other[..])
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: FmtAddress> FmtAddress for alloc::vec::Vec<T> {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self[..].fmt_address(f)
+    }
+}
+
+impl<T: Same> Same for Option<T> {
+    fn same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.same(b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: RefHash> RefHash for Option<T> {
+    fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+        self.is_some().hash(hasher);
+        if let Some(value) = self {
+            value.ref_hash(hasher);
+        }
+    }
+}
+
+/// `None` is ordered before `Some`.
+impl<T: RefOrd> RefOrd for Option<T> {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Some(a), Some(b)) => a.ref_cmp(b),
+            (a, b) => a.is_some().cmp(&b.is_some()),
+        }
+    }
+}
+
+impl<T: FmtAddress> FmtAddress for Option<T> {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Some(value) => f.debug_tuple("Some").field(&AddressOf(value)).finish(),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+/// Implements the traits for a tuple with the given element types and
+/// indices.
+macro_rules! tuple_impls {
+    ($($name:ident $idx:tt),+) => {
+        impl<$($name: Same),+> Same for ($($name,)+) {
+            fn same(&self, other: &Self) -> bool {
+                $(self.$idx.same(&other.$idx))&&+
+            }
+        }
+
+        impl<$($name: RefHash),+> RefHash for ($($name,)+) {
+            fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+                $(self.$idx.ref_hash(hasher);)+
+            }
+        }
+
+        impl<$($name: RefOrd),+> RefOrd for ($($name,)+) {
+            fn ref_cmp(&self, other: &Self) -> Ordering {
+                Ordering::Equal
+                    $(.then_with(|| self.$idx.ref_cmp(&other.$idx)))+
+            }
+        }
+
+        impl<$($name: FmtAddress),+> FmtAddress for ($($name,)+) {
+            fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.debug_tuple("")$(.field(&AddressOf(&self.$idx)))+.finish()
+            }
+        }
+    };
+}
+
+tuple_impls!(A 0);
+tuple_impls!(A 0, B 1);
+tuple_impls!(A 0, B 1, C 2);
+tuple_impls!(A 0, B 1, C 2, D 3);
+tuple_impls!(A 0, B 1, C 2, D 3, E 4);
+tuple_impls!(A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_impls!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_impls!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, I 7);
+tuple_impls!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, I 7, J 8);
+tuple_impls!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, I 7, J 8, K 9);
+tuple_impls!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, I 7, J 8, K 9, L 10);
+tuple_impls!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, I 7, J 8, K 9, L 10, M 11);
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use {RefCmp, RefOrd, Same};
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn tuples_and_options() {
+        use std::rc::Rc;
+
+        let a = Rc::new(1);
+        let b = Rc::new(2);
+        let b_equal = Rc::new(2);
+
+        let mut edges = HashSet::new();
+        assert!(edges.insert(RefCmp((a.clone(), b.clone()))));
+        assert!(!edges.insert(RefCmp((a.clone(), b.clone()))));
+        assert!(edges.insert(RefCmp((a.clone(), b_equal.clone()))));
+        assert!(edges.insert(RefCmp((b.clone(), a.clone()))));
+
+        assert!(None::<Rc<i32>>.same(&None));
+        assert!(Some(a.clone()).same(&Some(a.clone())));
+        assert!(!Some(a.clone()).same(&None));
+        assert!(!Some(b.clone()).same(&Some(b_equal)));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn sequences() {
+        use std::rc::Rc;
+
+        let a = Rc::new(1);
+        let b = Rc::new(2);
+
+        let children = [a.clone(), b.clone()].to_vec();
+        let same_children = [a.clone(), b.clone()].to_vec();
+        let prefix = [a.clone()].to_vec();
+
+        assert!(children.same(&same_children));
+        assert!(!children.same(&prefix));
+        assert!([a.clone(), b.clone()].same(&[a.clone(), b.clone()]));
+        assert!(!children[..].same(&[b.clone(), a.clone()][..]));
+
+        let mut set = HashSet::new();
+        assert!(set.insert(RefCmp(children)));
+        assert!(!set.insert(RefCmp(same_children)));
+        assert!(set.insert(RefCmp(prefix.clone())));
+
+        let mut sorted = ::std::collections::BTreeSet::new();
+        assert!(sorted.insert(RefCmp(prefix.clone())));
+        assert!(!sorted.insert(RefCmp(prefix)));
+    }
+
+    #[test]
+    fn ordering() {
+        use std::cmp::Ordering;
+
+        let array = [1, 2];
+        let (first, second) = (&array[0], &array[1]);
+
+        assert_eq!([first].ref_cmp(&[second]), Ordering::Less);
+        assert_eq!([first][..].ref_cmp(&[first, second][..]), Ordering::Less);
+        assert_eq!((first, second).ref_cmp(&(first, first)), Ordering::Greater);
+        assert_eq!(None.ref_cmp(&Some(first)), Ordering::Less);
+    }
+}
@@ -0,0 +1,127 @@
+//! `SameAs` implementations between different kinds of pointers.
+
+use alloc::rc::{self, Rc};
+use alloc::sync::{self, Arc};
+
+use {addr, Same, SameAs};
+
+/// Pointer which knows the address of its target.
+trait Address {
+    fn address(&self) -> usize;
+}
+
+impl<T: ?Sized> Address for &T {
+    fn address(&self) -> usize {
+        addr(*self)
+    }
+}
+
+impl<T: ?Sized> Address for Rc<T> {
+    fn address(&self) -> usize {
+        addr(Rc::as_ptr(self))
+    }
+}
+
+impl<T: ?Sized> Address for Arc<T> {
+    fn address(&self) -> usize {
+        addr(Arc::as_ptr(self))
+    }
+}
+
+impl<T: ?Sized> Address for rc::Weak<T> {
+    fn address(&self) -> usize {
+        addr(self.as_ptr())
+    }
+}
+
+impl<T: ?Sized> Address for sync::Weak<T> {
+    fn address(&self) -> usize {
+        addr(self.as_ptr())
+    }
+}
+
+/// Implements `SameAs` between strong pointers by comparing them as
+/// references.
+macro_rules! strong_impls {
+    ($($lhs:ty => $rhs:ty),* $(,)*) => {
+        $(
+            impl<T: ?Sized> SameAs<$rhs> for $lhs {
+                fn same_as(&self, other: &$rhs) -> bool {
+                    (&**self).same(&&**other)
+                }
+            }
+        )*
+    };
+}
+
+/// Implements `SameAs` in both directions between pointers where one of
+/// them is weak by comparing addresses.
+macro_rules! weak_impls {
+    ($($lhs:ty => $rhs:ty),* $(,)*) => {
+        $(
+            impl<T: ?Sized> SameAs<$rhs> for $lhs {
+                fn same_as(&self, other: &$rhs) -> bool {
+                    self.address() == other.address()
+                }
+            }
+
+            impl<T: ?Sized> SameAs<$lhs> for $rhs {
+                fn same_as(&self, other: &$lhs) -> bool {
+                    self.address() == other.address()
+                }
+            }
+        )*
+    };
+}
+
+strong_impls! {
+    &T => Rc<T>,
+    &T => Arc<T>,
+    Rc<T> => &T,
+    Rc<T> => Arc<T>,
+    Arc<T> => &T,
+    Arc<T> => Rc<T>,
+}
+
+weak_impls! {
+    rc::Weak<T> => &T,
+    rc::Weak<T> => Rc<T>,
+    rc::Weak<T> => Arc<T>,
+    rc::Weak<T> => sync::Weak<T>,
+    sync::Weak<T> => &T,
+    sync::Weak<T> => Rc<T>,
+    sync::Weak<T> => Arc<T>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::{self, Rc};
+    use std::sync::Arc;
+    use SameAs;
+
+    #[test]
+    fn combinations() {
+        let a = Rc::new([1, 2]);
+        let a_ref: &[i32; 2] = &a;
+        let a_slice: &[i32] = &a[..1];
+        let a_weak = Rc::downgrade(&a);
+        let b = Arc::new([1, 2]);
+        let b_weak = Arc::downgrade(&b);
+
+        assert!(a.same_as(&a_ref));
+        assert!(a_ref.same_as(&a));
+        assert!(a_weak.same_as(&a));
+        assert!(a.same_as(&a_weak));
+        assert!(a_ref.same_as(&a_weak));
+        assert!(a_weak.same_as(&a_ref));
+        assert!(b.same_as(&b_weak));
+        assert!(a.same_as(&a.clone()));
+
+        assert!(!a.same_as(&b));
+        assert!(!b.same_as(&a_ref));
+        assert!(!a_weak.same_as(&b_weak));
+        assert!(!a_weak.same_as(&rc::Weak::new()));
+        // Same address but different sizes.
+        assert!(!(&a[..]).same_as(&a_slice));
+    }
+}
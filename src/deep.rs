@@ -0,0 +1,517 @@
+//! Deep cloning of object graphs preserving sharing.
+//!
+//! Cloning an `Rc` only copies the handle, while cloning the values behind
+//! all `Rc`s one by one turns shared objects into separate copies: a diamond
+//! becomes a tree. `DeepClone` copies the values, but remembers the copy of
+//! each allocation (identified by `RefCmp` of its address), so the clone has
+//! exactly the same aliasing pattern as the original: objects that are
+//! shared in the original are shared in the clone as well and `Weak`
+//! pointers point to the copies of their original targets.
+//!
+//! # Weak pointers
+//!
+//! A `Weak` to an object that is reached from the value through strong
+//! pointers points to the copy of the object. To make this possible, such
+//! objects are cloned before the objects they strongly point to, even if
+//! they come later in the order of fields, and they are created using
+//! `Rc::new_cyclic`. This covers trees with parent pointers, nodes shared by
+//! several parents and other cycles through `Weak`. To find these objects,
+//! the value is cloned twice: the first clone only collects the graph of
+//! strong pointers and is thrown away. Thus deep cloning takes about twice
+//! as long as cloning the objects one by one.
+//!
+//! A `Weak` to an object that is not reached through strong pointers is not
+//! followed. The copy of the `Weak` keeps pointing to the original object,
+//! which is useful when cloning a subtree with pointers to its parent
+//! outside of the subtree.
+//!
+//! # Example
+//!
+//! ```
+//! use same::Same;
+//! use same::deep::{self, Cloner, DeepClone};
+//! use std::rc::Rc;
+//!
+//! struct Node {
+//!     value: u32,
+//!     children: Vec<Rc<Node>>,
+//! }
+//!
+//! impl DeepClone for Node {
+//!     fn deep_clone(&self, cloner: &mut Cloner) -> Self {
+//!         Node { value: self.value, children: self.children.deep_clone(cloner) }
+//!     }
+//! }
+//!
+//! let leaf = Rc::new(Node { value: 1, children: Vec::new() });
+//! let left = Rc::new(Node { value: 2, children: vec![leaf.clone()] });
+//! let right = Rc::new(Node { value: 3, children: vec![leaf] });
+//! let root = Rc::new(Node { value: 4, children: vec![left, right] });
+//!
+//! let copy = deep::deep_clone(&root);
+//! let (left, right) = (&copy.children[0], &copy.children[1]);
+//! // The leaf is still shared, but it's not the original one.
+//! assert!(left.children[0].same(&right.children[0]));
+//! assert!(!left.children[0].same(&root.children[0].children[0]));
+//! ```
+
+use core::any::Any;
+use core::cell::{Cell, RefCell};
+use std::boxed::Box;
+use std::collections::{HashMap, HashSet};
+use std::rc::{self, Rc};
+use std::string::String;
+use std::sync::{self, Arc};
+use std::vec::Vec;
+
+use shared::Shared;
+use RefCmp;
+
+/// Clones the value, preserving sharing of objects it points to.
+///
+/// # Panics
+///
+/// Panics if an object refers to itself through strong pointers.
+pub fn deep_clone<T: DeepClone>(value: &T) -> T {
+    Cloner::new().deep_clone(value)
+}
+
+/// Values that can be cloned deeply.
+///
+/// Implementations should clone the fields using `DeepClone` (passing the
+/// same cloner), so that shared pointers in them are cloned deeply too.
+/// Values are cloned twice (see the module documentation), so the
+/// implementations must clone the same fields each time.
+pub trait DeepClone: Sized {
+    /// Returns a deep clone of `self`.
+    fn deep_clone(&self, cloner: &mut Cloner) -> Self;
+}
+
+/// Closure cloning the target of a `Weak`.
+type CloneTarget = Rc<dyn Fn(&mut Cloner)>;
+
+/// Remembers copies of shared objects during cloning.
+pub struct Cloner {
+    /// Copies of objects by addresses of the originals. Objects that are
+    /// still being cloned are represented by a weak pointer.
+    copies: HashMap<RefCmp<*const ()>, Box<dyn Any>>,
+    /// Whether the cloner only collects the graph of the value.
+    collecting: bool,
+    /// Whether the graph of the value being cloned was collected.
+    collected: bool,
+    /// Objects which are being cloned.
+    stack: Vec<RefCmp<*const ()>>,
+    /// Objects strongly pointing to each object.
+    parents: HashMap<RefCmp<*const ()>, Vec<RefCmp<*const ()>>>,
+    /// Targets of `Weak`s which are reached through strong pointers.
+    weak_targets: HashMap<RefCmp<*const ()>, CloneTarget>,
+    /// Objects whose ancestors in `weak_targets` were already cloned or are
+    /// being cloned.
+    ready: HashSet<RefCmp<*const ()>>,
+}
+
+impl Cloner {
+    /// Creates a cloner that didn't clone anything yet.
+    ///
+    /// Values cloned using the same cloner share copies of shared objects.
+    /// However, a `Weak` pointing to an object that was not reached through
+    /// strong pointers from the value containing it keeps pointing to the
+    /// original, even if the object is cloned as a part of a later value.
+    pub fn new() -> Self {
+        Cloner {
+            copies: HashMap::new(),
+            collecting: false,
+            collected: false,
+            stack: Vec::new(),
+            parents: HashMap::new(),
+            weak_targets: HashMap::new(),
+            ready: HashSet::new(),
+        }
+    }
+
+    /// Clones the value, sharing copies of objects with previously cloned
+    /// values.
+    ///
+    /// Calling `value.deep_clone(cloner)` directly works as well, but then
+    /// each shared pointer in the value is treated as a separate value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an object refers to itself through strong pointers.
+    pub fn deep_clone<T: DeepClone>(&mut self, value: &T) -> T {
+        let mut collector = Cloner { collecting: true, ..Cloner::new() };
+        value.deep_clone(&mut collector);
+        let reached = collector.copies;
+        self.parents = collector.parents;
+        self.weak_targets = collector.weak_targets;
+        self.weak_targets.retain(|key, _| reached.contains_key(key));
+
+        self.collected = true;
+        let copy = value.deep_clone(self);
+        self.collected = false;
+        self.parents.clear();
+        self.weak_targets.clear();
+        self.ready.clear();
+        copy
+    }
+
+    fn clone_shared<P: Shared + DeepClone>(&mut self, ptr: &P) -> P where P::Target: DeepClone {
+        if !self.collecting && !self.collected {
+            return self.deep_clone(ptr);
+        }
+
+        let key = RefCmp(ptr.target() as *const P::Target as *const ());
+        if self.collecting {
+            if let Some(&parent) = self.stack.last() {
+                self.parents.entry(key).or_default().push(parent);
+            }
+        } else if !self.copies.contains_key(&key) {
+            self.clone_weak_targets(key);
+        }
+
+        if let Some(copy) = self.copies.get(&key) {
+            match copy.downcast_ref::<P>() {
+                Some(copy) => return copy.clone(),
+                None => panic!("object is part of a cycle of strong pointers"),
+            }
+        }
+
+        let copy = P::new_cyclic(|weak| {
+            self.copies.insert(key, Box::new(weak.clone()));
+            self.stack.push(key);
+            let value = ptr.target().deep_clone(self);
+            self.stack.pop();
+            value
+        });
+        self.copies.insert(key, Box::new(copy.clone()));
+        copy
+    }
+
+    fn clone_weak<P: Shared + DeepClone>(&mut self, weak: &P::Weak) -> P::Weak
+    where
+        P::Target: DeepClone,
+        P::Weak: DeepClone,
+    {
+        if !self.collecting && !self.collected {
+            return self.deep_clone(weak);
+        }
+
+        let target = match P::upgrade(weak) {
+            Some(target) => target,
+            None => return P::dangling(),
+        };
+        let key = RefCmp(P::weak_ptr(weak) as *const ());
+        if self.collecting {
+            self.weak_targets.entry(key).or_insert_with(|| Rc::new(move |cloner: &mut Cloner| {
+                cloner.clone_shared(&target);
+            }));
+            return weak.clone();
+        }
+
+        if !self.copies.contains_key(&key) {
+            if let Some(clone) = self.weak_targets.get(&key).cloned() {
+                clone(self);
+            }
+        }
+        let copy = match self.copies.get(&key) {
+            Some(copy) => copy,
+            None => return weak.clone(),
+        };
+        if let Some(copy) = copy.downcast_ref::<P>() {
+            return copy.downgrade();
+        }
+        match copy.downcast_ref::<P::Weak>() {
+            Some(copy) => copy.clone(),
+            None => weak.clone(),
+        }
+    }
+
+    /// Clones targets of `Weak`s which strongly point to the object, directly
+    /// or indirectly, so that they are being cloned when the `Weak`s pointing
+    /// to them are reached from the object.
+    ///
+    /// Each object is walked through at most once until it's cloned or ready,
+    /// so this takes linear time in total.
+    fn clone_weak_targets(&mut self, key: RefCmp<*const ()>) {
+        let mut index = 0;
+        while let Some(&parent) = self.parents.get(&key).and_then(|parents| parents.get(index)) {
+            index += 1;
+            if self.copies.contains_key(&parent) || self.ready.contains(&parent) {
+                continue;
+            }
+            self.clone_weak_targets(parent);
+            match self.weak_targets.get(&parent).cloned() {
+                Some(clone) => clone(self),
+                None => {
+                    self.ready.insert(parent);
+                },
+            }
+        }
+    }
+}
+
+impl Default for Cloner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+macro_rules! copy_impls {
+    ($($ty:ty),*) => {
+        $(
+            impl DeepClone for $ty {
+                fn deep_clone(&self, _: &mut Cloner) -> Self {
+                    *self
+                }
+            }
+        )*
+    };
+}
+
+copy_impls!(bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, ());
+
+impl DeepClone for String {
+    fn deep_clone(&self, _: &mut Cloner) -> Self {
+        self.clone()
+    }
+}
+
+impl<T: DeepClone> DeepClone for Vec<T> {
+    fn deep_clone(&self, cloner: &mut Cloner) -> Self {
+        self.iter().map(|item| item.deep_clone(cloner)).collect()
+    }
+}
+
+impl<T: DeepClone> DeepClone for Option<T> {
+    fn deep_clone(&self, cloner: &mut Cloner) -> Self {
+        self.as_ref().map(|value| value.deep_clone(cloner))
+    }
+}
+
+impl<T: DeepClone> DeepClone for Box<T> {
+    fn deep_clone(&self, cloner: &mut Cloner) -> Self {
+        Box::new((**self).deep_clone(cloner))
+    }
+}
+
+/// Panics if the value is mutably borrowed.
+impl<T: DeepClone> DeepClone for RefCell<T> {
+    fn deep_clone(&self, cloner: &mut Cloner) -> Self {
+        RefCell::new(self.borrow().deep_clone(cloner))
+    }
+}
+
+impl<T: DeepClone + Copy> DeepClone for Cell<T> {
+    fn deep_clone(&self, cloner: &mut Cloner) -> Self {
+        Cell::new(self.get().deep_clone(cloner))
+    }
+}
+
+macro_rules! tuple_impls {
+    ($($name:ident $idx:tt),+) => {
+        impl<$($name: DeepClone),+> DeepClone for ($($name,)+) {
+            fn deep_clone(&self, cloner: &mut Cloner) -> Self {
+                ($(self.$idx.deep_clone(cloner),)+)
+            }
+        }
+    };
+}
+
+tuple_impls!(A 0);
+tuple_impls!(A 0, B 1);
+tuple_impls!(A 0, B 1, C 2);
+tuple_impls!(A 0, B 1, C 2, D 3);
+
+/// Clones the object only once, later occurrences share the copy.
+///
+/// # Panics
+///
+/// Panics if the object refers to itself through strong pointers.
+impl<T: DeepClone + 'static> DeepClone for Rc<T> {
+    fn deep_clone(&self, cloner: &mut Cloner) -> Self {
+        cloner.clone_shared(self)
+    }
+}
+
+/// Clones the object only once, later occurrences share the copy.
+///
+/// # Panics
+///
+/// Panics if the object refers to itself through strong pointers.
+impl<T: DeepClone + 'static> DeepClone for Arc<T> {
+    fn deep_clone(&self, cloner: &mut Cloner) -> Self {
+        cloner.clone_shared(self)
+    }
+}
+
+/// Points to the copy of the target if it's reached through strong pointers,
+/// otherwise points to the original target.
+impl<T: DeepClone + 'static> DeepClone for rc::Weak<T> {
+    fn deep_clone(&self, cloner: &mut Cloner) -> Self {
+        cloner.clone_weak::<Rc<T>>(self)
+    }
+}
+
+/// Points to the copy of the target if it's reached through strong pointers,
+/// otherwise points to the original target.
+impl<T: DeepClone + 'static> DeepClone for sync::Weak<T> {
+    fn deep_clone(&self, cloner: &mut Cloner) -> Self {
+        cloner.clone_weak::<Arc<T>>(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deep_clone, Cloner, DeepClone};
+    use std::cell::RefCell;
+    use std::rc::{Rc, Weak};
+    use std::sync::Arc;
+    use std::vec::Vec;
+    use Same;
+
+    struct Node {
+        id: u32,
+        parent: RefCell<Weak<Node>>,
+        children: RefCell<Vec<Rc<Node>>>,
+    }
+
+    impl Node {
+        fn new(id: u32) -> Rc<Self> {
+            Rc::new(Node { id, parent: RefCell::new(Weak::new()), children: RefCell::new(Vec::new()) })
+        }
+
+        fn add(self: &Rc<Self>, child: &Rc<Node>) {
+            *child.parent.borrow_mut() = Rc::downgrade(self);
+            self.children.borrow_mut().push(child.clone());
+        }
+
+        fn child(&self, index: usize) -> Rc<Node> {
+            self.children.borrow()[index].clone()
+        }
+
+        fn parent(&self) -> Rc<Node> {
+            self.parent.borrow().upgrade().unwrap()
+        }
+    }
+
+    impl DeepClone for Node {
+        fn deep_clone(&self, cloner: &mut Cloner) -> Self {
+            Node {
+                id: self.id,
+                parent: self.parent.deep_clone(cloner),
+                children: self.children.deep_clone(cloner),
+            }
+        }
+    }
+
+    #[test]
+    fn sharing() {
+        let shared = Arc::new(1u32);
+        let value = ([shared.clone(), Arc::new(1), shared].to_vec(), Some(Arc::new(2u32)));
+
+        let copy = deep_clone(&value);
+        assert!(copy.0[0].same(&copy.0[2]));
+        assert!(!copy.0[0].same(&copy.0[1]));
+        assert!(!copy.0[0].same(&value.0[0]));
+        assert_eq!(*copy.1.unwrap(), 2);
+    }
+
+    #[test]
+    fn cycles() {
+        let root = Node::new(0);
+        let (a, b, c) = (Node::new(1), Node::new(2), Node::new(3));
+        root.add(&a);
+        root.add(&b);
+        b.add(&c);
+        a.add(&c);
+
+        let copy = deep_clone(&root);
+        let (a, b) = (copy.child(0), copy.child(1));
+        assert_eq!((a.id, b.id), (1, 2));
+        assert!(a.parent().same(&copy));
+        assert!(a.child(0).same(&b.child(0)));
+        assert!(b.child(0).parent().same(&a));
+        assert!(!copy.same(&root));
+    }
+
+    #[test]
+    fn subtree() {
+        let root = Node::new(0);
+        let (a, b) = (Node::new(1), Node::new(2));
+        root.add(&a);
+        a.add(&b);
+
+        // The parent of the copied subtree is not cloned.
+        let copy = deep_clone(&a);
+        assert!(copy.parent().same(&root));
+        assert!(copy.child(0).parent().same(&copy));
+        assert_eq!(root.children.borrow().len(), 1);
+    }
+
+    #[test]
+    fn weak_before_strong() {
+        let root = Node::new(0);
+        let (a, b, c) = (Node::new(1), Node::new(2), Node::new(3));
+        root.add(&a);
+        root.add(&b);
+        a.add(&c);
+        b.add(&c);
+
+        // The parent of `c` comes after `c` in the order of fields.
+        for copy in [deep_clone(&root), root.deep_clone(&mut Cloner::new())].iter() {
+            let (a, b) = (copy.child(0), copy.child(1));
+            assert_eq!((a.id, b.id), (1, 2));
+            assert!(a.parent().same(copy));
+            assert!(b.parent().same(copy));
+            assert!(a.child(0).same(&b.child(0)));
+            assert!(a.child(0).parent().same(&b));
+            assert!(!b.same(&root.child(1)));
+        }
+    }
+
+    #[test]
+    fn weak_to_owner_of_ancestor() {
+        let root = Node::new(0);
+        let (a, b, c) = (Node::new(1), Node::new(2), Node::new(3));
+        root.add(&a);
+        root.add(&c);
+        a.add(&b);
+        c.children.borrow_mut().push(a.clone());
+        *b.parent.borrow_mut() = Rc::downgrade(&c);
+
+        // `c` has to be cloned before `a`, not just before `b`.
+        let copy = deep_clone(&root);
+        let (a, c) = (copy.child(0), copy.child(1));
+        assert!(c.child(0).same(&a));
+        assert!(a.parent().same(&copy));
+        assert!(a.child(0).parent().same(&c));
+    }
+
+    #[test]
+    fn interleaved_weak_before_strong() {
+        // Each of `shared` is a child of two `parents`, but its parent
+        // pointer points to the later one.
+        let root = Node::new(0);
+        let parents = (1..6).map(Node::new).collect::<Vec<_>>();
+        let shared = (6..10).map(Node::new).collect::<Vec<_>>();
+        for parent in &parents {
+            root.add(parent);
+        }
+        for (index, node) in shared.iter().enumerate() {
+            parents[index].add(node);
+            parents[index + 1].add(node);
+        }
+
+        let copy = deep_clone(&root);
+        let parents = (0..5).map(|index| copy.child(index)).collect::<Vec<_>>();
+        for (index, parent) in parents.iter().enumerate() {
+            assert!(parent.parent().same(&copy));
+            if let Some(next) = parents.get(index + 1) {
+                let node = parent.child(parent.children.borrow().len() - 1);
+                assert_eq!(node.id, index as u32 + 6);
+                assert!(node.same(&next.child(0)));
+                assert!(node.parent().same(next));
+            }
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! Extension traits for identity operations on iterators, slices and vectors.
+//!
+//! The methods mirror the standard ones (`contains`, `position`, `dedup`,
+//! ...), but compare items using `Same` instead of `PartialEq`.
+//!
+//! Removing duplicates needs to remember the items seen so far. With the `std`
+//! feature this uses a hash set, so it runs in O(n). Without it, items are
+//! compared with all previous ones, which runs in O(n²) but doesn't allocate
+//! (apart from the iterator adapter, which needs `alloc` to store the items).
+//!
+//! Note that `iter()` of a slice yields references, which are themselves
+//! compared by address, so `handles.iter().contains_same(&&handle)` tests
+//! whether `handle` is an element of the slice rather than whether it points
+//! to the same object as one of the elements. Use the slice methods or
+//! `.cloned()` in such cases.
+//!
+//! # Example
+//!
+//! ```
+//! use same::ext::{SameSliceExt, SameVecExt};
+//! use std::rc::Rc;
+//!
+//! let a = Rc::new(1);
+//! let b = Rc::new(1);
+//! let mut handles = vec![a.clone(), b.clone(), a.clone(), a.clone()];
+//!
+//! assert_eq!(handles.position_same(&b), Some(1));
+//! assert!(!handles.contains_same(&Rc::new(1)));
+//! assert_eq!(handles.group_same().map(|group| group.len()).collect::<Vec<_>>(), [1, 1, 2]);
+//!
+//! handles.dedup_same();
+//! assert_eq!(handles.len(), 2);
+//! assert_eq!(handles.remove_same(&a), 1);
+//! assert!(handles.all_same());
+//! ```
+
+#[cfg(feature = "std")]
+use std::collections::HashSet;
+
+#[cfg(feature = "alloc")]
+use RefHash;
+#[cfg(feature = "std")]
+use RefCmp;
+use Same;
+
+/// Identity operations on iterators.
+pub trait SameIterExt: Iterator + Sized {
+    /// Returns `true` if any item is the same as `item`.
+    fn contains_same(mut self, item: &Self::Item) -> bool where Self::Item: Same {
+        self.any(|other| other.same(item))
+    }
+
+    /// Returns the index of the first item which is the same as `item`.
+    fn position_same(mut self, item: &Self::Item) -> Option<usize> where Self::Item: Same {
+        self.position(|other| other.same(item))
+    }
+
+    /// Returns `true` if all items are the same object.
+    ///
+    /// Returns `true` for empty iterators.
+    fn all_same(mut self) -> bool where Self::Item: Same {
+        match self.next() {
+            Some(first) => self.all(|item| item.same(&first)),
+            None => true,
+        }
+    }
+
+    /// Groups consecutive items which are the same object.
+    ///
+    /// The returned iterator yields the first item of each group together
+    /// with the length of the group.
+    fn group_same(self) -> GroupSame<Self> where Self::Item: Same {
+        GroupSame { iter: self, next: None }
+    }
+
+    /// Skips items which are the same as a previous item, keeping the order.
+    ///
+    /// The returned iterator keeps clones of the yielded items.
+    #[cfg(feature = "alloc")]
+    fn dedup_same(self) -> DedupSame<Self> where Self::Item: Same + RefHash + Clone {
+        DedupSame { iter: self, seen: Default::default() }
+    }
+}
+
+impl<I: Iterator> SameIterExt for I {}
+
+/// Identity operations on slices.
+pub trait SameSliceExt<T> {
+    /// Returns `true` if the slice contains an element which is the same as
+    /// `item`.
+    fn contains_same(&self, item: &T) -> bool;
+
+    /// Returns the index of the first element which is the same as `item`.
+    fn position_same(&self, item: &T) -> Option<usize>;
+
+    /// Returns `true` if all elements are the same object.
+    ///
+    /// Returns `true` for empty slices.
+    fn all_same(&self) -> bool;
+
+    /// Splits the slice into runs of consecutive elements which are the same
+    /// object.
+    fn group_same(&self) -> SliceGroupSame<'_, T>;
+}
+
+impl<T: Same> SameSliceExt<T> for [T] {
+    fn contains_same(&self, item: &T) -> bool {
+        self.iter().any(|other| other.same(item))
+    }
+
+    fn position_same(&self, item: &T) -> Option<usize> {
+        self.iter().position(|other| other.same(item))
+    }
+
+    fn all_same(&self) -> bool {
+        self.windows(2).all(|pair| pair[0].same(&pair[1]))
+    }
+
+    fn group_same(&self) -> SliceGroupSame<'_, T> {
+        SliceGroupSame { slice: self }
+    }
+}
+
+/// Identity operations on vectors.
+#[cfg(feature = "alloc")]
+pub trait SameVecExt<T> {
+    /// Removes all elements which are the same as `item` and returns their
+    /// number.
+    fn remove_same(&mut self, item: &T) -> usize;
+
+    /// Removes elements which are the same as a previous element, keeping
+    /// the order.
+    fn dedup_same(&mut self);
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Same + RefHash> SameVecExt<T> for alloc::vec::Vec<T> {
+    fn remove_same(&mut self, item: &T) -> usize {
+        let len = self.len();
+        self.retain(|other| !other.same(item));
+        len - self.len()
+    }
+
+    #[cfg(feature = "std")]
+    fn dedup_same(&mut self) {
+        let mut seen = HashSet::with_capacity(self.len());
+        let keep: alloc::vec::Vec<bool> = self.iter().map(|item| seen.insert(RefCmp::from_ref(item))).collect();
+        drop(seen);
+        let mut keep = keep.into_iter();
+        self.retain(|_| keep.next().unwrap_or(true));
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn dedup_same(&mut self) {
+        let mut kept = 0;
+        for i in 0..self.len() {
+            if !self[..kept].iter().any(|item| item.same(&self[i])) {
+                self.swap(kept, i);
+                kept += 1;
+            }
+        }
+        self.truncate(kept);
+    }
+}
+
+/// Iterator returned by `SameIterExt::group_same`.
+pub struct GroupSame<I: Iterator> {
+    iter: I,
+    next: Option<I::Item>,
+}
+
+impl<I: Iterator> Iterator for GroupSame<I> where I::Item: Same {
+    type Item = (I::Item, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.next.take().or_else(|| self.iter.next())?;
+        let mut len = 1;
+        for item in &mut self.iter {
+            if !item.same(&first) {
+                self.next = Some(item);
+                break;
+            }
+            len += 1;
+        }
+        Some((first, len))
+    }
+}
+
+/// Iterator returned by `SameIterExt::dedup_same`.
+#[cfg(feature = "alloc")]
+pub struct DedupSame<I: Iterator> {
+    iter: I,
+    #[cfg(feature = "std")]
+    seen: HashSet<RefCmp<I::Item>>,
+    #[cfg(not(feature = "std"))]
+    seen: alloc::vec::Vec<I::Item>,
+}
+
+#[cfg(feature = "alloc")]
+impl<I: Iterator> Iterator for DedupSame<I> where I::Item: Same + RefHash + Clone {
+    type Item = I::Item;
+
+    #[cfg(feature = "std")]
+    fn next(&mut self) -> Option<Self::Item> {
+        let seen = &mut self.seen;
+        self.iter.find(|item| seen.insert(RefCmp(item.clone())))
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn next(&mut self) -> Option<Self::Item> {
+        let seen = &mut self.seen;
+        let item = self.iter.find(|item| !seen.iter().any(|other| other.same(item)))?;
+        seen.push(item.clone());
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+/// Iterator returned by `SameSliceExt::group_same`.
+pub struct SliceGroupSame<'a, T: 'a> {
+    slice: &'a [T],
+}
+
+impl<'a, T: Same> Iterator for SliceGroupSame<'a, T> {
+    type Item = &'a [T];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.slice.first()?;
+        let len = self.slice.iter().take_while(|item| (*item).same(first)).count();
+        let (group, rest) = self.slice.split_at(len);
+        self.slice = rest;
+        Some(group)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SameIterExt, SameSliceExt};
+    use Same;
+
+    #[test]
+    fn iterators() {
+        let values = [1, 1, 2];
+        let (a, b, c) = (&values[0], &values[1], &values[2]);
+        let items = [a, a, b, a, c, c];
+
+        assert!(items.iter().cloned().contains_same(&b));
+        assert!(!items.iter().cloned().contains_same(&&1));
+        assert_eq!(items.iter().cloned().position_same(&c), Some(4));
+        assert!(items[..2].iter().cloned().all_same());
+        assert!(!items.iter().cloned().all_same());
+
+        let mut groups = items.iter().cloned().group_same();
+        let (item, len) = groups.next().unwrap();
+        assert!(item.same(&a) && len == 2);
+        let (item, len) = groups.next().unwrap();
+        assert!(item.same(&b) && len == 1);
+        assert_eq!(groups.map(|(_, len)| len).sum::<usize>(), 3);
+    }
+
+    #[test]
+    fn slices() {
+        let values = [1, 1];
+        let (a, b) = (&values[0], &values[1]);
+        let items = [a, b, b, a];
+
+        assert!(items.contains_same(&b));
+        assert_eq!(items.position_same(&b), Some(1));
+        assert!(!items.all_same());
+        assert!([a, a].all_same());
+        assert!(<[&i32]>::all_same(&[]));
+        assert_eq!(items.group_same().map(|group| group.len()).collect::<::std::vec::Vec<_>>(), [1, 2, 1]);
+        assert_eq!(items[..0].group_same().count(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn vectors() {
+        use super::SameVecExt;
+        use std::rc::Rc;
+        use std::vec::Vec;
+
+        let a = Rc::new(1);
+        let b = Rc::new(1);
+        let c = Rc::new(2);
+        let handles = [a.clone(), b.clone(), a.clone(), c.clone(), b.clone()].to_vec();
+
+        let unique: Vec<_> = handles.iter().cloned().dedup_same().collect();
+        assert_eq!(unique.len(), 3);
+        assert!(unique[0].same(&a) && unique[1].same(&b) && unique[2].same(&c));
+
+        let mut deduped = handles.clone();
+        deduped.dedup_same();
+        assert!(deduped.same(&unique));
+
+        let mut removed = handles;
+        assert_eq!(removed.remove_same(&b), 2);
+        assert_eq!(removed.remove_same(&Rc::new(1)), 0);
+        assert!(removed.same(&[a.clone(), a, c].to_vec()));
+    }
+}
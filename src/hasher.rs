@@ -0,0 +1,146 @@
+//! Fast hasher for identity keys.
+//!
+//! See `IdentityHasher` for more information.
+
+use core::hash::{BuildHasher, Hasher};
+
+/// Odd constant derived from the golden ratio, which spreads bits well.
+const MULTIPLIER: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Hasher optimized for hashing addresses.
+///
+/// `RefHash` implementations feed just one pointer-sized integer into the
+/// hasher. General-purpose hashers such as SipHash are needlessly slow for
+/// this, since they are designed to resist hash flooding. Addresses chosen
+/// by the allocator can't be easily controlled by an attacker, so a much
+/// cheaper function is good enough.
+///
+/// The hasher multiplies the input by a large odd constant and folds the high
+/// half of the 128-bit product into the low half. This moves the entropy of
+/// the middle bits of the address into both low and high bits of the hash, so
+/// the low bits, which are always zero because of alignment, don't cause
+/// collisions in hash tables.
+///
+/// The hasher accepts arbitrary input, but it's only intended for keys that
+/// hash a few integers. Its quality is poor for long byte strings and it
+/// must **not** be used for keys controlled by an attacker.
+///
+/// # Example
+///
+/// ```
+/// use same::{IdentityBuildHasher, RefCmp};
+///
+/// use std::collections::HashSet;
+/// use std::sync::Arc;
+///
+/// let a = Arc::new(42);
+///
+/// let mut set = HashSet::with_hasher(IdentityBuildHasher::default());
+/// assert!(set.insert(RefCmp(a.clone())));
+/// assert!(!set.insert(RefCmp(a)));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct IdentityHasher {
+    state: u64,
+}
+
+impl IdentityHasher {
+    fn mix(&mut self, value: u64) {
+        let full = u128::from(self.state ^ value) * u128::from(MULTIPLIER);
+        self.state = (full as u64) ^ ((full >> 64) as u64);
+    }
+}
+
+impl Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.state
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(chunk);
+            self.mix(u64::from_le_bytes(buf));
+        }
+
+        let remainder = chunks.remainder();
+        if !remainder.is_empty() {
+            let mut buf = [0; 8];
+            buf[..remainder.len()].copy_from_slice(remainder);
+            self.mix(u64::from_le_bytes(buf));
+        }
+    }
+
+    fn write_u8(&mut self, value: u8) {
+        self.mix(u64::from(value))
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        self.mix(u64::from(value))
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.mix(u64::from(value))
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.mix(value)
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.mix(value as u64)
+    }
+}
+
+/// Creates `IdentityHasher`s.
+///
+/// Use this as the `S` parameter of `HashMap`, `HashSet`, `IdentityMap` or
+/// `IdentitySet` to make hashing of identity keys cheap. All instances
+/// produce the same hashers, so the hash values are deterministic.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentityBuildHasher;
+
+impl BuildHasher for IdentityBuildHasher {
+    type Hasher = IdentityHasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        IdentityHasher::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdentityHasher;
+    use core::hash::Hasher;
+    use RefHash;
+
+    fn hash<T: RefHash>(value: &T) -> u64 {
+        let mut hasher = IdentityHasher::default();
+        value.ref_hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn low_bits_of_addresses_are_mixed() {
+        // The values don't matter, the hashed keys are the addresses of the
+        // elements, which are aligned to 8 bytes.
+        let values = [0u64; 64];
+
+        // Buckets of hash tables are usually selected by low bits.
+        let mut buckets = [false; 64];
+        for address in &values {
+            buckets[(hash(&address) % 64) as usize] = true;
+        }
+        assert!(buckets.iter().filter(|&&used| used).count() > 32);
+    }
+
+    #[test]
+    fn bytes() {
+        let mut a = IdentityHasher::default();
+        a.write(b"hello world");
+        let mut b = IdentityHasher::default();
+        b.write(b"hello worle");
+        assert_ne!(a.finish(), b.finish());
+    }
+}
@@ -0,0 +1,242 @@
+//! Object ids which can outlive the objects.
+//!
+//! Addresses are reused after objects are deallocated, so an address saved
+//! in a log or a side table may later match a completely different object.
+//! `ObjectId` is a plain `Copy` id based on the address. It's suitable for
+//! objects that are known to be alive. `LiveId` and `SyncLiveId` pair the
+//! id with a `Weak`, which keeps the allocation (but not the value) alive.
+//! The address can't be reused while they exist, so they never match a
+//! different object and they can tell whether the original object is still
+//! alive.
+//!
+//! # Example
+//!
+//! ```
+//! use same::id::{LiveId, ObjectId};
+//! use std::rc::Rc;
+//!
+//! let object = Rc::new(42);
+//! let id = ObjectId::of(&object);
+//! let live_id = LiveId::new(&object);
+//!
+//! assert!(id.is(&object));
+//! assert!(live_id.is(&object));
+//! assert_eq!(live_id.id(), id);
+//!
+//! drop(object);
+//! // A new object may now get the same address and thus match `id`, but
+//! // `live_id` knows the original is gone.
+//! assert!(!live_id.is_alive());
+//! assert!(!live_id.is(&Rc::new(42)));
+//! ```
+
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::mem;
+use core::ops::Deref;
+
+use addr;
+
+/// Id of an object based on its address.
+///
+/// The id is valid only while the object is alive, after that it may match
+/// another object allocated at the same address. Use `LiveId` or
+/// `SyncLiveId` if the id may outlive the object.
+///
+/// Ids compare, hash and order the same way as `&T` does using `Same`,
+/// `RefHash` and `RefOrd`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ObjectId {
+    addr: usize,
+    size: usize,
+}
+
+impl ObjectId {
+    /// Returns the id of the object the handle points to.
+    pub fn of<P: Deref>(handle: &P) -> Self {
+        Self::of_ref(&**handle)
+    }
+
+    /// Returns the id of the referenced object.
+    ///
+    /// Note that `ObjectId::of(&handle)` takes the id of the object `handle`
+    /// points to, while `ObjectId::of_ref(&handle)` takes the id of `handle`
+    /// itself.
+    pub fn of_ref<T: ?Sized>(target: &T) -> Self {
+        ObjectId { addr: addr(target), size: mem::size_of_val(target) }
+    }
+
+    /// Returns `true` if the handle points to an object with this id.
+    pub fn is<P: Deref>(&self, handle: &P) -> bool {
+        *self == Self::of(handle)
+    }
+
+    /// Returns the address of the object.
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+}
+
+/// Hashes only the address, consistently with `RefHash` for `&T`.
+impl Hash for ObjectId {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        self.addr.hash(hasher);
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ObjectId({:#x})", self.addr)
+    }
+}
+
+#[cfg(feature = "alloc")]
+macro_rules! live_id {
+    ($(#[$attr:meta])* $name:ident, $ptr:ident, $module:ident) => {
+        $(#[$attr])*
+        pub struct $name<T: ?Sized> {
+            id: ObjectId,
+            weak: alloc::$module::Weak<T>,
+        }
+
+        impl<T: ?Sized> $name<T> {
+            /// Creates the id of the object the handle points to.
+            pub fn new(handle: &alloc::$module::$ptr<T>) -> Self {
+                $name { id: ObjectId::of(handle), weak: alloc::$module::$ptr::downgrade(handle) }
+            }
+
+            /// Returns the plain id of the object.
+            pub fn id(&self) -> ObjectId {
+                self.id
+            }
+
+            /// Returns `true` if the object wasn't dropped yet.
+            pub fn is_alive(&self) -> bool {
+                self.weak.strong_count() > 0
+            }
+
+            /// Returns a handle to the object if it's still alive.
+            pub fn upgrade(&self) -> Option<alloc::$module::$ptr<T>> {
+                self.weak.upgrade()
+            }
+
+            /// Returns `true` if the handle points to the object this id was
+            /// created from.
+            ///
+            /// This never matches objects allocated after the original one
+            /// was dropped.
+            pub fn is(&self, handle: &alloc::$module::$ptr<T>) -> bool {
+                self.id.is(handle)
+            }
+        }
+
+        impl<T: ?Sized> Clone for $name<T> {
+            fn clone(&self) -> Self {
+                $name { id: self.id, weak: self.weak.clone() }
+            }
+        }
+
+        impl<T: ?Sized> Eq for $name<T> {}
+        impl<T: ?Sized> PartialEq for $name<T> {
+            fn eq(&self, other: &Self) -> bool {
+                self.id == other.id
+            }
+        }
+
+        impl<T: ?Sized> Hash for $name<T> {
+            fn hash<H: Hasher>(&self, hasher: &mut H) {
+                self.id.hash(hasher);
+            }
+        }
+
+        impl<T: ?Sized> PartialOrd for $name<T> {
+            fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl<T: ?Sized> Ord for $name<T> {
+            fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+                self.id.cmp(&other.id)
+            }
+        }
+
+        impl<T: ?Sized> fmt::Debug for $name<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.debug_struct(stringify!($name))
+                    .field("id", &self.id)
+                    .field("alive", &self.is_alive())
+                    .finish()
+            }
+        }
+    };
+}
+
+#[cfg(feature = "alloc")]
+live_id! {
+    /// Id of an object behind an `Rc` which can detect that the object was
+    /// dropped.
+    ///
+    /// The id holds a `Weak`, so the address of the object can't be reused
+    /// while the id exists. Thus it never matches another object, unlike
+    /// `ObjectId`. Note that the memory of the object isn't released until
+    /// all ids are dropped.
+    LiveId, Rc, rc
+}
+
+#[cfg(feature = "alloc")]
+live_id! {
+    /// Id of an object behind an `Arc` which can detect that the object was
+    /// dropped.
+    ///
+    /// This is the thread-safe version of `LiveId`.
+    SyncLiveId, Arc, sync
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ObjectId;
+
+    #[test]
+    fn object_ids() {
+        let array = [1, 2];
+        let id = ObjectId::of_ref(&array);
+
+        assert!(id.is(&&array));
+        assert_eq!(id, ObjectId::of_ref(&array[..]));
+        assert_ne!(id, ObjectId::of_ref(&array[..1]));
+        assert!(ObjectId::of_ref(&array[0]) < ObjectId::of_ref(&array[1]));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn live_ids() {
+        use super::{LiveId, SyncLiveId};
+        use std::collections::HashSet;
+        use std::rc::Rc;
+        use std::sync::Arc;
+        use Same;
+
+        let a = Rc::new(1);
+        let b = Rc::new(1);
+        let mut ids = HashSet::new();
+        assert!(ids.insert(LiveId::new(&a)));
+        assert!(!ids.insert(LiveId::new(&a.clone())));
+        assert!(ids.insert(LiveId::new(&b)));
+
+        let id = LiveId::new(&a);
+        assert!(id.upgrade().unwrap().same(&a));
+        drop(a);
+        assert!(!id.is_alive());
+        assert!(id.upgrade().is_none());
+        // The allocation is still reserved, so new objects get other addresses.
+        for _ in 0..16 {
+            assert!(!id.is(&Rc::new(1)));
+        }
+
+        let c = Arc::new(1);
+        let sync_id = SyncLiveId::new(&c);
+        assert!(sync_id.is(&c));
+        assert_eq!(sync_id.id(), ObjectId::of(&c));
+    }
+}
@@ -0,0 +1,565 @@
+//! Insertion-ordered hash map keyed by identity of objects.
+//!
+//! See `IdentityIndexMap` for more information.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::iter::FromIterator;
+use core::mem;
+use core::ops::Index;
+use core::slice;
+use std::collections::hash_map::{HashMap, RandomState};
+use std::vec::{self, Vec};
+
+use {IdentityBuildHasher, Lookup, RefCmp, RefHash, Same};
+
+/// Hash map comparing keys by identity, which remembers the insertion order.
+///
+/// Like `IdentityMap`, the keys can be any type implementing `Same` and
+/// `RefHash`, two keys are considered equal if they are the same, and the
+/// map can be looked up by a reference to a key or, for handles, by a plain
+/// reference to the object. Unlike `IdentityMap`, iteration yields the entries
+/// in the order they were inserted, which doesn't depend on the addresses of
+/// the objects, so it's deterministic. The entries can also be accessed by
+/// their index.
+///
+/// The entries are stored in a vector and a hash table maps the hashes of the
+/// keys to indices. Lookups and `swap_remove` run in O(1), while
+/// `shift_remove` preserves the order of the remaining entries and thus runs
+/// in O(n).
+///
+/// # Example
+///
+/// ```
+/// use same::IdentityIndexMap;
+/// use std::rc::Rc;
+///
+/// let a = Rc::new(42);
+/// let b = Rc::new(42);
+///
+/// let mut map = IdentityIndexMap::new();
+/// map.insert(b.clone(), "b");
+/// map.insert(a.clone(), "a");
+///
+/// assert_eq!(map.get(&a), Some(&"a"));
+/// assert_eq!(map.get_index_of(&b), Some(0));
+/// assert_eq!(map.values().collect::<Vec<_>>(), [&"b", &"a"]);
+/// ```
+pub struct IdentityIndexMap<K, V, S = RandomState> {
+    entries: Vec<(K, V)>,
+    /// Indices of the entries by the hashes of their keys.
+    indices: HashMap<u64, Indices, IdentityBuildHasher>,
+    hash_builder: S,
+}
+
+/// Indices of the entries whose keys have the same hash.
+///
+/// Different keys almost never collide, so a single index is stored without
+/// allocating.
+#[derive(Clone)]
+enum Indices {
+    One(usize),
+    Many(Vec<usize>),
+}
+
+impl Indices {
+    fn as_slice(&self) -> &[usize] {
+        match self {
+            Indices::One(index) => slice::from_ref(index),
+            Indices::Many(indices) => indices,
+        }
+    }
+
+    fn push(&mut self, index: usize) {
+        match self {
+            Indices::One(first) => *self = Indices::Many([*first, index].to_vec()),
+            Indices::Many(indices) => indices.push(index),
+        }
+    }
+
+    /// Removes the index and returns `true` if no indices are left.
+    fn remove(&mut self, index: usize) -> bool {
+        match self {
+            Indices::One(_) => true,
+            Indices::Many(indices) => {
+                indices.retain(|&other| other != index);
+                if let [last] = indices[..] {
+                    *self = Indices::One(last);
+                }
+                false
+            },
+        }
+    }
+
+    fn replace(&mut self, old: usize, new: usize) {
+        match self {
+            Indices::One(index) => *index = new,
+            Indices::Many(indices) => indices.iter_mut().filter(|index| **index == old).for_each(|index| *index = new),
+        }
+    }
+
+    fn iter_mut(&mut self) -> slice::IterMut<'_, usize> {
+        match self {
+            Indices::One(index) => slice::from_mut(index).iter_mut(),
+            Indices::Many(indices) => indices.iter_mut(),
+        }
+    }
+}
+
+impl<K: Same + RefHash, V> IdentityIndexMap<K, V, RandomState> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+
+    /// Creates an empty map with at least the specified capacity.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S> IdentityIndexMap<K, V, S> {
+    /// Creates an empty map which will use the given hash builder.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates an empty map with at least the specified capacity, using
+    /// `hash_builder` to hash the keys.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        IdentityIndexMap {
+            entries: Vec::with_capacity(capacity),
+            indices: HashMap::with_capacity_and_hasher(capacity, IdentityBuildHasher),
+            hash_builder,
+        }
+    }
+
+    /// Returns a reference to the map's `BuildHasher`.
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Returns the number of elements in the map.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the map contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Removes all elements from the map.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.indices.clear();
+    }
+
+    /// Iterates over key-value pairs in insertion order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { iter: self.entries.iter() }
+    }
+
+    /// Iterates over key-value pairs in insertion order, with mutable
+    /// references to the values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut { iter: self.entries.iter_mut() }
+    }
+
+    /// Iterates over keys in insertion order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { iter: self.entries.iter() }
+    }
+
+    /// Iterates over values in insertion order.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { iter: self.entries.iter() }
+    }
+
+    /// Iterates over mutable references to values in insertion order.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut { iter: self.entries.iter_mut() }
+    }
+
+    /// Returns the key-value pair at the given index.
+    pub fn get_index(&self, index: usize) -> Option<(&K, &V)> {
+        self.entries.get(index).map(|(key, value)| (key, value))
+    }
+
+    /// Returns the key and a mutable reference to the value at the given
+    /// index.
+    pub fn get_index_mut(&mut self, index: usize) -> Option<(&K, &mut V)> {
+        self.entries.get_mut(index).map(|(key, value)| (&*key, value))
+    }
+
+    /// Returns the first key-value pair.
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.get_index(0)
+    }
+
+    /// Returns the last key-value pair.
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.entries.last().map(|(key, value)| (key, value))
+    }
+}
+
+impl<K: Same + RefHash, V, S: BuildHasher> IdentityIndexMap<K, V, S> {
+    /// Reserves capacity for at least `additional` more elements.
+    pub fn reserve(&mut self, additional: usize) {
+        self.entries.reserve(additional);
+        self.indices.reserve(additional);
+    }
+
+    /// Inserts a key-value pair at the end of the map.
+    ///
+    /// If the map already contained a key which is the same, the value is
+    /// updated in place and the old value is returned. The key and the
+    /// position of the entry are not updated.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert_full(key, value).1
+    }
+
+    /// Inserts a key-value pair like `insert` and also returns the index of
+    /// the entry.
+    pub fn insert_full(&mut self, key: K, value: V) -> (usize, Option<V>) {
+        if let Some(index) = self.get_index_of(&key) {
+            return (index, Some(mem::replace(&mut self.entries[index].1, value)));
+        }
+
+        let index = self.entries.len();
+        let hash = self.hash(RefCmp::from_ref(&key));
+        self.indices.entry(hash).and_modify(|indices| indices.push(index)).or_insert(Indices::One(index));
+        self.entries.push((key, value));
+        (index, None)
+    }
+
+    /// Returns the index of the key in the map.
+    pub fn get_index_of<Q: ?Sized + Lookup<K>>(&self, key: &Q) -> Option<usize> where RefCmp<K>: Borrow<Q::Key> {
+        let key = key.lookup_key();
+        let indices = self.indices.get(&self.hash(key))?;
+        indices.as_slice().iter().copied().find(|&index| RefCmp::from_ref(&self.entries[index].0).borrow() == key)
+    }
+
+    /// Returns a reference to the value associated with the key.
+    pub fn get<Q: ?Sized + Lookup<K>>(&self, key: &Q) -> Option<&V> where RefCmp<K>: Borrow<Q::Key> {
+        self.get_index_of(key).map(|index| &self.entries[index].1)
+    }
+
+    /// Returns the stored key and the value associated with the key.
+    pub fn get_key_value<Q: ?Sized + Lookup<K>>(&self, key: &Q) -> Option<(&K, &V)> where RefCmp<K>: Borrow<Q::Key> {
+        self.get_index_of(key).and_then(|index| self.get_index(index))
+    }
+
+    /// Returns a mutable reference to the value associated with the key.
+    pub fn get_mut<Q: ?Sized + Lookup<K>>(&mut self, key: &Q) -> Option<&mut V> where RefCmp<K>: Borrow<Q::Key> {
+        let index = self.get_index_of(key)?;
+        Some(&mut self.entries[index].1)
+    }
+
+    /// Returns `true` if the map contains a key which is the same as `key`.
+    pub fn contains_key<Q: ?Sized + Lookup<K>>(&self, key: &Q) -> bool where RefCmp<K>: Borrow<Q::Key> {
+        self.get_index_of(key).is_some()
+    }
+
+    /// Removes the key from the map by swapping it with the last entry,
+    /// returning the associated value.
+    ///
+    /// This changes the order of the entries, but runs in O(1).
+    pub fn swap_remove<Q: ?Sized + Lookup<K>>(&mut self, key: &Q) -> Option<V> where RefCmp<K>: Borrow<Q::Key> {
+        let index = self.get_index_of(key)?;
+        self.swap_remove_index(index).map(|(_, value)| value)
+    }
+
+    /// Removes the key from the map by shifting all following entries,
+    /// returning the associated value.
+    ///
+    /// This preserves the order of the remaining entries, but runs in O(n).
+    pub fn shift_remove<Q: ?Sized + Lookup<K>>(&mut self, key: &Q) -> Option<V> where RefCmp<K>: Borrow<Q::Key> {
+        let index = self.get_index_of(key)?;
+        self.shift_remove_index(index).map(|(_, value)| value)
+    }
+
+    /// Removes the entry at the given index by swapping it with the last
+    /// entry.
+    pub fn swap_remove_index(&mut self, index: usize) -> Option<(K, V)> {
+        if index >= self.entries.len() {
+            return None;
+        }
+        let (key, value) = self.entries.swap_remove(index);
+        self.remove_index(&key, index);
+        if let Some((moved, _)) = self.entries.get(index) {
+            let hash = self.hash(RefCmp::from_ref(moved));
+            if let Some(indices) = self.indices.get_mut(&hash) {
+                indices.replace(self.entries.len(), index);
+            }
+        }
+        Some((key, value))
+    }
+
+    /// Removes the entry at the given index by shifting all following
+    /// entries.
+    pub fn shift_remove_index(&mut self, index: usize) -> Option<(K, V)> {
+        if index >= self.entries.len() {
+            return None;
+        }
+        let (key, value) = self.entries.remove(index);
+        self.remove_index(&key, index);
+        for indices in self.indices.values_mut() {
+            indices.iter_mut().filter(|other| **other > index).for_each(|other| *other -= 1);
+        }
+        Some((key, value))
+    }
+
+    /// Removes the last entry and returns it.
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        let (key, value) = self.entries.pop()?;
+        self.remove_index(&key, self.entries.len());
+        Some((key, value))
+    }
+
+    /// Retains only the elements for which the predicate returns `true`,
+    /// preserving their order.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        let len = self.entries.len();
+        self.entries.retain_mut(|(key, value)| f(key, value));
+        if self.entries.len() < len {
+            self.indices.clear();
+            for index in 0..self.entries.len() {
+                let hash = self.hash(RefCmp::from_ref(&self.entries[index].0));
+                self.indices.entry(hash).and_modify(|indices| indices.push(index)).or_insert(Indices::One(index));
+            }
+        }
+    }
+
+    fn hash<Q: ?Sized + Hash>(&self, key: &Q) -> u64 {
+        self.hash_builder.hash_one(key)
+    }
+
+    /// Removes the index of the entry with the given key from the table.
+    fn remove_index(&mut self, key: &K, index: usize) {
+        let hash = self.hash(RefCmp::from_ref(key));
+        let is_empty = self.indices.get_mut(&hash).is_some_and(|indices| indices.remove(index));
+        if is_empty {
+            self.indices.remove(&hash);
+        }
+    }
+}
+
+impl<K: Clone, V: Clone, S: Clone> Clone for IdentityIndexMap<K, V, S> {
+    fn clone(&self) -> Self {
+        IdentityIndexMap {
+            entries: self.entries.clone(),
+            indices: self.indices.clone(),
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+}
+
+impl<K: Same + RefHash, V, S: Default> Default for IdentityIndexMap<K, V, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for IdentityIndexMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S, Q> Index<&Q> for IdentityIndexMap<K, V, S>
+where
+    K: Same + RefHash,
+    S: BuildHasher,
+    Q: ?Sized + Lookup<K>,
+    RefCmp<K>: Borrow<Q::Key>,
+{
+    type Output = V;
+
+    /// Returns a reference to the value associated with the key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not present in the map.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("key not found in IdentityIndexMap")
+    }
+}
+
+impl<K: Same + RefHash, V, S: BuildHasher> Extend<(K, V)> for IdentityIndexMap<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Same + RefHash, V, S: BuildHasher + Default> FromIterator<(K, V)> for IdentityIndexMap<K, V, S> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = IdentityIndexMap::with_hasher(S::default());
+        map.extend(iter);
+        map
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a IdentityIndexMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut IdentityIndexMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, S> IntoIterator for IdentityIndexMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { iter: self.entries.into_iter() }
+    }
+}
+
+/// Implements the iterator traits for an iterator wrapping a vector or
+/// slice iterator of entries.
+macro_rules! iterator_impls {
+    ($name:ident<$($lt:lifetime),*>, $item:ty, |$entry:pat| $map:expr) => {
+        impl<$($lt,)* K, V> Iterator for $name<$($lt,)* K, V> {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.iter.next().map(|$entry| $map)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.iter.size_hint()
+            }
+        }
+
+        impl<$($lt,)* K, V> DoubleEndedIterator for $name<$($lt,)* K, V> {
+            fn next_back(&mut self) -> Option<Self::Item> {
+                self.iter.next_back().map(|$entry| $map)
+            }
+        }
+
+        impl<$($lt,)* K, V> ExactSizeIterator for $name<$($lt,)* K, V> {}
+    };
+}
+
+/// Iterator over key-value pairs of `IdentityIndexMap`.
+pub struct Iter<'a, K: 'a, V: 'a> {
+    iter: slice::Iter<'a, (K, V)>,
+}
+
+iterator_impls!(Iter<'a>, (&'a K, &'a V), |(key, value)| (key, value));
+
+/// Iterator over key-value pairs of `IdentityIndexMap` with mutable
+/// references to the values.
+pub struct IterMut<'a, K: 'a, V: 'a> {
+    iter: slice::IterMut<'a, (K, V)>,
+}
+
+iterator_impls!(IterMut<'a>, (&'a K, &'a mut V), |(key, value)| (&*key, value));
+
+/// Iterator over keys of `IdentityIndexMap`.
+pub struct Keys<'a, K: 'a, V: 'a> {
+    iter: slice::Iter<'a, (K, V)>,
+}
+
+iterator_impls!(Keys<'a>, &'a K, |(key, _)| key);
+
+/// Iterator over values of `IdentityIndexMap`.
+pub struct Values<'a, K: 'a, V: 'a> {
+    iter: slice::Iter<'a, (K, V)>,
+}
+
+iterator_impls!(Values<'a>, &'a V, |(_, value)| value);
+
+/// Iterator over mutable references to values of `IdentityIndexMap`.
+pub struct ValuesMut<'a, K: 'a, V: 'a> {
+    iter: slice::IterMut<'a, (K, V)>,
+}
+
+iterator_impls!(ValuesMut<'a>, &'a mut V, |(_, value)| value);
+
+/// Owning iterator over key-value pairs of `IdentityIndexMap`.
+pub struct IntoIter<K, V> {
+    iter: vec::IntoIter<(K, V)>,
+}
+
+iterator_impls!(IntoIter<>, (K, V), |entry| entry);
+
+#[cfg(test)]
+mod tests {
+    use super::IdentityIndexMap;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    #[test]
+    fn order_and_indices() {
+        let handles: Vec<_> = (0..5).map(Rc::new).collect();
+        let mut map = handles.iter().cloned().zip(0..).collect::<IdentityIndexMap<_, _>>();
+
+        assert_eq!(map.insert(handles[1].clone(), 10), Some(1));
+        assert_eq!(map.insert_full(Rc::new(1), 5), (5, None));
+        assert_eq!(map.get_index_of(&handles[3]), Some(3));
+        assert_eq!(map[&*handles[1]], 10);
+        assert_eq!(map.get_index(2).map(|(_, value)| *value), Some(2));
+        assert!(Rc::ptr_eq(map.get_index(4).unwrap().0, &handles[4]));
+        assert!(map.get_index(6).is_none());
+        assert!(!map.contains_key(&1));
+
+        *map.get_index_mut(0).unwrap().1 = 7;
+        *map.get_mut(&handles[2]).unwrap() += 20;
+        assert_eq!(map.values().cloned().collect::<Vec<_>>(), [7, 10, 22, 3, 4, 5]);
+        assert_eq!(map.values().next_back(), Some(&5));
+    }
+
+    #[test]
+    fn removal() {
+        let handles: Vec<_> = (0..5).map(Rc::new).collect();
+        let mut map = handles.iter().cloned().zip(0..).collect::<IdentityIndexMap<_, _>>();
+
+        assert_eq!(map.swap_remove(&handles[1]), Some(1));
+        assert_eq!(map.swap_remove(&handles[1]), None);
+        assert_eq!(map.values().cloned().collect::<Vec<_>>(), [0, 4, 2, 3]);
+        assert_eq!(map.get_index_of(&handles[4]), Some(1));
+
+        assert_eq!(map.shift_remove(&handles[0]), Some(0));
+        assert_eq!(map.values().cloned().collect::<Vec<_>>(), [4, 2, 3]);
+        assert_eq!(map.get_index_of(&handles[3]), Some(2));
+
+        assert!(map.swap_remove_index(2).is_some());
+        assert!(map.shift_remove_index(2).is_none());
+        assert_eq!(map.pop().map(|(_, value)| value), Some(2));
+        assert_eq!(map.len(), 1);
+        assert_eq!(map[&handles[4]], 4);
+
+        let mut map = handles.iter().cloned().zip(0..).collect::<IdentityIndexMap<_, _>>();
+        map.retain(|_, value| *value % 2 == 0);
+        assert_eq!(map.keys().map(|key| **key).collect::<Vec<_>>(), [0, 2, 4]);
+        assert_eq!(map.get_index_of(&handles[4]), Some(2));
+        assert_eq!(Rc::strong_count(&handles[1]), 1);
+    }
+
+    #[test]
+    fn weak_keys() {
+        let handles: Vec<_> = (0..3).map(Rc::new).collect();
+        let mut map = handles.iter().map(Rc::downgrade).zip(0..).collect::<IdentityIndexMap<_, _>>();
+
+        assert_eq!(map.get_index_of(&Rc::downgrade(&handles[2])), Some(2));
+        assert_eq!(map.swap_remove(&Rc::downgrade(&handles[0])), Some(0));
+        assert_eq!(map[&Rc::downgrade(&handles[2])], 2);
+        assert!(!map.contains_key(&Rc::downgrade(&Rc::new(2))));
+    }
+}
@@ -0,0 +1,320 @@
+//! Insertion-ordered hash set of objects compared by identity.
+//!
+//! See `IdentityIndexSet` for more information.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::BuildHasher;
+use core::iter::FromIterator;
+use core::ops::Index;
+use std::collections::hash_map::RandomState;
+
+use index_map::{self, IdentityIndexMap};
+use {Lookup, RefCmp, RefHash, Same};
+
+/// Hash set comparing elements by identity, which remembers the insertion
+/// order.
+///
+/// This is the set counterpart of `IdentityIndexMap`: iteration yields the
+/// elements in the order they were inserted and the elements can be
+/// accessed by their index.
+///
+/// # Example
+///
+/// ```
+/// use same::IdentityIndexSet;
+/// use std::rc::Rc;
+///
+/// let a = Rc::new("a");
+/// let b = Rc::new("b");
+///
+/// let mut set = IdentityIndexSet::new();
+/// assert!(set.insert(b.clone()));
+/// assert!(set.insert(a.clone()));
+/// assert!(!set.insert(b.clone()));
+///
+/// assert_eq!(set.get_index_of(&a), Some(1));
+/// assert_eq!(set.iter().map(|item| **item).collect::<Vec<_>>(), ["b", "a"]);
+/// ```
+pub struct IdentityIndexSet<T, S = RandomState> {
+    map: IdentityIndexMap<T, (), S>,
+}
+
+impl<T: Same + RefHash> IdentityIndexSet<T, RandomState> {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        IdentityIndexSet { map: IdentityIndexMap::new() }
+    }
+
+    /// Creates an empty set with at least the specified capacity.
+    pub fn with_capacity(capacity: usize) -> Self {
+        IdentityIndexSet { map: IdentityIndexMap::with_capacity(capacity) }
+    }
+}
+
+impl<T, S> IdentityIndexSet<T, S> {
+    /// Creates an empty set which will use the given hash builder.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        IdentityIndexSet { map: IdentityIndexMap::with_hasher(hash_builder) }
+    }
+
+    /// Creates an empty set with at least the specified capacity, using
+    /// `hash_builder` to hash the elements.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        IdentityIndexSet { map: IdentityIndexMap::with_capacity_and_hasher(capacity, hash_builder) }
+    }
+
+    /// Returns a reference to the set's `BuildHasher`.
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the set contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all elements from the set.
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    /// Iterates over the elements in insertion order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { iter: self.map.keys() }
+    }
+
+    /// Returns the element at the given index.
+    pub fn get_index(&self, index: usize) -> Option<&T> {
+        self.map.get_index(index).map(|(key, _)| key)
+    }
+
+    /// Returns the first element.
+    pub fn first(&self) -> Option<&T> {
+        self.map.first().map(|(key, _)| key)
+    }
+
+    /// Returns the last element.
+    pub fn last(&self) -> Option<&T> {
+        self.map.last().map(|(key, _)| key)
+    }
+}
+
+impl<T: Same + RefHash, S: BuildHasher> IdentityIndexSet<T, S> {
+    /// Reserves capacity for at least `additional` more elements.
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional)
+    }
+
+    /// Adds the element at the end of the set.
+    ///
+    /// Returns `true` if the set didn't contain an element which is the same.
+    /// The stored element and its position are not updated.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.map.insert(value, ()).is_none()
+    }
+
+    /// Adds the element like `insert` and also returns its index.
+    pub fn insert_full(&mut self, value: T) -> (usize, bool) {
+        let (index, old) = self.map.insert_full(value, ());
+        (index, old.is_none())
+    }
+
+    /// Returns `true` if the set contains an element which is the same as
+    /// `value`.
+    pub fn contains<Q: ?Sized + Lookup<T>>(&self, value: &Q) -> bool where RefCmp<T>: Borrow<Q::Key> {
+        self.map.contains_key(value)
+    }
+
+    /// Returns the stored element which is the same as `value`.
+    pub fn get<Q: ?Sized + Lookup<T>>(&self, value: &Q) -> Option<&T> where RefCmp<T>: Borrow<Q::Key> {
+        self.map.get_key_value(value).map(|(key, _)| key)
+    }
+
+    /// Returns the index of the element in the set.
+    pub fn get_index_of<Q: ?Sized + Lookup<T>>(&self, value: &Q) -> Option<usize> where RefCmp<T>: Borrow<Q::Key> {
+        self.map.get_index_of(value)
+    }
+
+    /// Removes the element from the set by swapping it with the last element,
+    /// returning `true` if it was present.
+    ///
+    /// This changes the order of the elements, but runs in O(1).
+    pub fn swap_remove<Q: ?Sized + Lookup<T>>(&mut self, value: &Q) -> bool where RefCmp<T>: Borrow<Q::Key> {
+        self.map.swap_remove(value).is_some()
+    }
+
+    /// Removes the element from the set by shifting all following elements,
+    /// returning `true` if it was present.
+    ///
+    /// This preserves the order of the remaining elements, but runs in O(n).
+    pub fn shift_remove<Q: ?Sized + Lookup<T>>(&mut self, value: &Q) -> bool where RefCmp<T>: Borrow<Q::Key> {
+        self.map.shift_remove(value).is_some()
+    }
+
+    /// Removes the element at the given index by swapping it with the last
+    /// element.
+    pub fn swap_remove_index(&mut self, index: usize) -> Option<T> {
+        self.map.swap_remove_index(index).map(|(key, _)| key)
+    }
+
+    /// Removes the element at the given index by shifting all following
+    /// elements.
+    pub fn shift_remove_index(&mut self, index: usize) -> Option<T> {
+        self.map.shift_remove_index(index).map(|(key, _)| key)
+    }
+
+    /// Removes the last element and returns it.
+    pub fn pop(&mut self) -> Option<T> {
+        self.map.pop().map(|(key, _)| key)
+    }
+
+    /// Retains only the elements for which the predicate returns `true`,
+    /// preserving their order.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        self.map.retain(|key, _| f(key))
+    }
+}
+
+impl<T: Clone, S: Clone> Clone for IdentityIndexSet<T, S> {
+    fn clone(&self) -> Self {
+        IdentityIndexSet { map: self.map.clone() }
+    }
+}
+
+impl<T: Same + RefHash, S: Default> Default for IdentityIndexSet<T, S> {
+    fn default() -> Self {
+        IdentityIndexSet { map: IdentityIndexMap::default() }
+    }
+}
+
+impl<T: fmt::Debug, S> fmt::Debug for IdentityIndexSet<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T, S> Index<usize> for IdentityIndexSet<T, S> {
+    type Output = T;
+
+    /// Returns the element at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    fn index(&self, index: usize) -> &T {
+        self.get_index(index).expect("index out of bounds of IdentityIndexSet")
+    }
+}
+
+impl<T: Same + RefHash, S: BuildHasher> Extend<T> for IdentityIndexSet<T, S> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.map.extend(iter.into_iter().map(|value| (value, ())))
+    }
+}
+
+impl<T: Same + RefHash, S: BuildHasher + Default> FromIterator<T> for IdentityIndexSet<T, S> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        IdentityIndexSet { map: iter.into_iter().map(|value| (value, ())).collect() }
+    }
+}
+
+impl<'a, T, S> IntoIterator for &'a IdentityIndexSet<T, S> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, S> IntoIterator for IdentityIndexSet<T, S> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { iter: self.map.into_iter() }
+    }
+}
+
+/// Iterator over elements of `IdentityIndexSet`.
+pub struct Iter<'a, T: 'a> {
+    iter: index_map::Keys<'a, T, ()>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+/// Owning iterator over elements of `IdentityIndexSet`.
+pub struct IntoIter<T> {
+    iter: index_map::IntoIter<T, ()>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::IdentityIndexSet;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    #[test]
+    fn order_and_removal() {
+        let handles: Vec<_> = (0..4).map(Rc::new).collect();
+        let mut set = handles.iter().rev().cloned().collect::<IdentityIndexSet<_>>();
+
+        assert_eq!(set.insert_full(handles[2].clone()), (1, false));
+        assert!(!set.contains(&2));
+        assert!(Rc::ptr_eq(&set[0], &handles[3]));
+        assert!(Rc::ptr_eq(set.get(&handles[1]).unwrap(), &handles[1]));
+
+        assert!(set.swap_remove(&handles[3]));
+        assert_eq!(set.iter().map(|item| **item).collect::<Vec<_>>(), [0, 2, 1]);
+        assert!(set.shift_remove(&handles[0]));
+        assert!(!set.shift_remove(&handles[0]));
+        assert_eq!(set.get_index_of(&handles[1]), Some(1));
+
+        set.retain(|item| **item != 2);
+        assert_eq!(set.into_iter().map(|item| *item).collect::<Vec<_>>(), [1]);
+    }
+}
@@ -0,0 +1,287 @@
+//! Hash-consing of values.
+//!
+//! Interning makes sure that structurally equal values share a single
+//! allocation. Handles returned by the interners are plain `Rc`s or `Arc`s,
+//! so comparing them using `Same` (which is O(1)) gives the same result as
+//! comparing the values using `PartialEq`, as long as they come from the
+//! same interner. They can also be hashed using `RefHash` and stored in
+//! `IdentityMap` or `IdentitySet`.
+//!
+//! Both `Interner` and `SyncInterner` support unsized values such as `str`
+//! or `[T]`, so they can be used as symbol tables.
+//!
+//! # Example
+//!
+//! ```
+//! use same::Same;
+//! use same::intern::Interner;
+//!
+//! let mut symbols = Interner::<str>::new();
+//! let foo = symbols.intern("foo");
+//! let bar = symbols.intern(String::from("bar"));
+//! let foo_again = symbols.intern(String::from("foo"));
+//!
+//! assert!(foo.same(&foo_again));
+//! assert!(!foo.same(&bar));
+//! ```
+
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use std::collections::hash_map::RandomState;
+use std::collections::hash_set::{self, HashSet};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Minimum number of entries before unused ones are purged automatically.
+const MIN_PURGE_THRESHOLD: usize = 32;
+
+/// Handle that knows whether it's shared.
+trait Handle {
+    fn is_shared(&self) -> bool;
+}
+
+impl<T: ?Sized> Handle for Rc<T> {
+    fn is_shared(&self) -> bool {
+        Rc::strong_count(self) > 1
+    }
+}
+
+impl<T: ?Sized> Handle for Arc<T> {
+    fn is_shared(&self) -> bool {
+        Arc::strong_count(self) > 1
+    }
+}
+
+/// Implementation shared by both interners.
+struct Table<P, S> {
+    set: HashSet<P, S>,
+    purge_threshold: usize,
+}
+
+impl<P: Handle, S> Table<P, S> {
+    fn with_hasher(hash_builder: S) -> Self {
+        Table { set: HashSet::with_hasher(hash_builder), purge_threshold: MIN_PURGE_THRESHOLD }
+    }
+
+    fn purge(&mut self) {
+        self.set.retain(Handle::is_shared);
+        self.purge_threshold = MIN_PURGE_THRESHOLD.max(self.set.len() * 2);
+    }
+}
+
+impl<P: Handle + Clone + Eq + Hash, S: BuildHasher> Table<P, S> {
+    fn intern<T, Q>(&mut self, value: Q) -> P where T: ?Sized + Eq + Hash, P: Borrow<T>, Q: Borrow<T> + Into<P> {
+        if let Some(canonical) = self.set.get(value.borrow()) {
+            return canonical.clone();
+        }
+
+        if self.set.len() >= self.purge_threshold {
+            self.purge();
+        }
+        let canonical = value.into();
+        self.set.insert(canonical.clone());
+        canonical
+    }
+}
+
+/// Interner producing `Rc` handles.
+///
+/// Entries which are only referenced by the interner are reclaimed when
+/// `purge` is called. This also happens automatically whenever the number of
+/// entries doubles since the previous purge, so the cost is amortized.
+pub struct Interner<T: ?Sized, S = RandomState> {
+    table: Table<Rc<T>, S>,
+}
+
+impl<T: ?Sized + Eq + Hash> Interner<T, RandomState> {
+    /// Creates an empty interner.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<T: ?Sized, S> Interner<T, S> {
+    /// Creates an empty interner which will use the given hash builder.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Interner { table: Table::with_hasher(hash_builder) }
+    }
+
+    /// Returns the number of entries, including the unused ones that weren't
+    /// purged yet.
+    pub fn len(&self) -> usize {
+        self.table.set.len()
+    }
+
+    /// Returns `true` if the interner contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.table.set.is_empty()
+    }
+
+    /// Iterates over all canonical handles in arbitrary order.
+    pub fn iter(&self) -> hash_set::Iter<'_, Rc<T>> {
+        self.table.set.iter()
+    }
+
+    /// Removes entries which are not referenced by anything else than the
+    /// interner.
+    pub fn purge(&mut self) {
+        self.table.purge()
+    }
+}
+
+impl<T: ?Sized + Eq + Hash, S: BuildHasher> Interner<T, S> {
+    /// Returns the canonical handle of the value.
+    ///
+    /// If an equal value was interned before and it's still in use, the
+    /// handle to it is returned. Otherwise the value is converted into `Rc`
+    /// and remembered.
+    ///
+    /// The value can be anything convertible into `Rc<T>`, which allows
+    /// passing both `&str` and `String` for `T = str`.
+    pub fn intern<Q: Borrow<T> + Into<Rc<T>>>(&mut self, value: Q) -> Rc<T> {
+        self.table.intern(value)
+    }
+
+    /// Returns the canonical handle of the value if there's one.
+    pub fn get(&self, value: &T) -> Option<&Rc<T>> {
+        self.table.set.get(value)
+    }
+}
+
+impl<T: ?Sized + Eq + Hash, S: BuildHasher + Default> Default for Interner<T, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+/// Thread-safe interner producing `Arc` handles.
+///
+/// This works just like `Interner`, but it can be shared between threads.
+/// All methods take `&self` and synchronize using a mutex.
+pub struct SyncInterner<T: ?Sized, S = RandomState> {
+    table: Mutex<Table<Arc<T>, S>>,
+}
+
+impl<T: ?Sized + Eq + Hash> SyncInterner<T, RandomState> {
+    /// Creates an empty interner.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<T: ?Sized, S> SyncInterner<T, S> {
+    /// Creates an empty interner which will use the given hash builder.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        SyncInterner { table: Mutex::new(Table::with_hasher(hash_builder)) }
+    }
+
+    /// Returns the number of entries, including the unused ones that weren't
+    /// purged yet.
+    pub fn len(&self) -> usize {
+        self.lock().set.len()
+    }
+
+    /// Returns `true` if the interner contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.lock().set.is_empty()
+    }
+
+    /// Removes entries which are not referenced by anything else than the
+    /// interner.
+    pub fn purge(&self) {
+        self.lock().purge()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Table<Arc<T>, S>> {
+        // The set stays consistent even if hashing or comparison panicked.
+        self.table.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+impl<T: ?Sized + Eq + Hash, S: BuildHasher> SyncInterner<T, S> {
+    /// Returns the canonical handle of the value.
+    ///
+    /// See `Interner::intern`.
+    pub fn intern<Q: Borrow<T> + Into<Arc<T>>>(&self, value: Q) -> Arc<T> {
+        self.lock().intern(value)
+    }
+
+    /// Returns the canonical handle of the value if there's one.
+    pub fn get(&self, value: &T) -> Option<Arc<T>> {
+        self.lock().set.get(value).cloned()
+    }
+}
+
+impl<T: ?Sized + Eq + Hash, S: BuildHasher + Default> Default for SyncInterner<T, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interner, SyncInterner};
+    use std::string::{String, ToString};
+    use std::sync::Arc;
+    use std::thread;
+    use std::vec::Vec;
+    use Same;
+
+    #[test]
+    fn sharing() {
+        let mut interner = Interner::<Vec<u32>>::new();
+        let a = interner.intern(vec_of(&[1, 2]));
+        let b = interner.intern(vec_of(&[1, 2]));
+        let c = interner.intern(vec_of(&[3]));
+
+        assert!(a.same(&b));
+        assert!(!a.same(&c));
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn slices() {
+        let mut interner = Interner::<[u8]>::new();
+        let a = interner.intern(&b"abc"[..]);
+        let b = interner.intern(Vec::from(&b"abc"[..]));
+
+        assert!(a.same(&b));
+        assert!(interner.get(b"abc").unwrap().same(&a));
+        assert!(interner.get(b"xyz").is_none());
+    }
+
+    #[test]
+    fn reclamation() {
+        let mut interner = Interner::<str>::new();
+        let kept = interner.intern("kept");
+        drop(interner.intern(String::from("dropped")));
+        assert_eq!(interner.len(), 2);
+
+        interner.purge();
+        assert_eq!(interner.len(), 1);
+        assert!(interner.intern("kept").same(&kept));
+
+        // Unused entries are purged automatically as the interner grows.
+        for i in 0..1000u32 {
+            interner.intern(i.to_string());
+        }
+        assert!(interner.len() < 100);
+    }
+
+    #[test]
+    fn threads() {
+        let interner = Arc::new(SyncInterner::<str>::new());
+        let handles = (0..4).map(|_| {
+            let interner = interner.clone();
+            thread::spawn(move || interner.intern("shared"))
+        }).collect::<Vec<_>>();
+        let symbols = handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>();
+
+        assert!(symbols.iter().all(|symbol| symbol.same(&symbols[0])));
+        assert_eq!(interner.len(), 1);
+    }
+
+    fn vec_of(values: &[u32]) -> Vec<u32> {
+        values.to_vec()
+    }
+}
@@ -1,21 +1,173 @@
 //! This crate provides mainly the trait `Same` which can be used on *shared*
-//! reference types. (`&T`, `Rc`, `Arc`). It enables the users to test identity
+//! reference types. (`&T`, `Rc`, `Arc`, `Weak`). It enables the users to test identity
 //! of objects. It's analogous to `PartialEq`, which tests *equality* instead.
 //! 
 //! Additionally, this crate provides `RefHash` trait, which is used for hashing
-//! references and `RefCmp` wrapper struct, which implements `Eq`, `PartialEq`
-//! and `Hash` by delegating to `Same` and `RefHash` traits. This is mainly
-//! useful if one wants to store objects in `HashSet` or similar data structure,
-//! with.
+//! references, `RefOrd` trait, which is used for ordering references by their
+//! addresses and `RefCmp` wrapper struct, which implements `Eq`, `PartialEq`,
+//! `Hash` and `Ord` by delegating to `Same`, `RefHash` and `RefOrd` traits.
+//! This is mainly useful if one wants to store objects in `HashSet`, `BTreeSet`
+//! or similar data structure.
+//! `RefKey` is the borrowed form of such keys, so for example a
+//! `HashSet<RefCmp<Rc<T>>>` can be queried by `&T` or `&Rc<T>` without cloning.
+//!
+//! `StrictRefCmp` is a stricter variant of `RefCmp`, which also compares
+//! vtables of trait objects.
+//!
+//! `assert_same!` and `assert_not_same!` (and their `debug_assert_*` variants)
+//! test identity in tests. On failure they print the addresses and, if possible,
+//! the values, and point out objects that are equal but distinct.
+//!
+//! `SameAs` generalizes `Same` to compare different kinds of pointers, such as
+//! `Rc<T>` with `&T` or `Weak<T>`.
+//!
+//! Pinned shared pointers implement the traits too. Pinned unique pointers
+//! (`Pin<Box<T>>`, `Pin<&mut T>`) can't be shared, but since their address
+//! is stable, `PinnedId` can be taken from them and used as an identity key.
+//!
+//! `ObjectId` is a `Copy` id of an object. Since addresses are reused after
+//! objects are dropped, the `id` module also provides `LiveId`, which holds a
+//! `Weak`, so it can detect that the object is gone and never matches a new
+//! object allocated at the same address.
+//! With the `std` feature, `WeakKeyMap` in the `weak` module builds on this to
+//! attach data to objects without keeping them alive.
+//!
+//! The `ext` module provides extension traits for iterators, slices and vectors,
+//! such as `contains_same`, `position_same`, `dedup_same` or `group_same`.
+//!
+//! The `region` module answers related questions about memory occupied by
+//! objects, such as whether a reference points into a slice and at which index,
+//! or whether two slices overlap.
+//!
+//! With the `std` feature, `IdentityMap` and `IdentitySet` collections are
+//! available as more convenient alternatives to `HashMap<RefCmp<K>, V>` and
+//! `HashSet<RefCmp<T>>`. The `visit` module builds on them to traverse graphs
+//! of shared pointers, visiting each node exactly once even if the graph has
+//! cycles.
+//!
+//! `IdentityIndexMap` and `IdentityIndexSet` are their insertion-ordered
+//! variants with index-based access, which iterate deterministically regardless
+//! of the addresses of the objects.
+//!
+//! The `intern` module provides interners, which make structurally equal values
+//! share one allocation, so they can be compared using `Same` in O(1).
+//!
+//! The `codec` module serializes graphs of `Rc` and `Arc` pointers, writing
+//! each shared object only once, and restores the same sharing (including
+//! cycles through `Weak`) when decoding.
+//! Similarly, the `deep` module clones such graphs deeply, keeping the same
+//! sharing in the copy.
+//!
+//! `IdentityBuildHasher` is a fast, `no_std`-compatible hasher for identity
+//! keys, which is much cheaper than the default SipHash.
+//!
+//! The `derive` feature enables `#[derive(Same, RefHash)]` for newtypes around
+//! shared pointers, which forward the identity to a single field.
 //! 
-//! This crate is `no_std`-compatible.
+//! This crate is `no_std`-compatible. Implementations for `Rc`, `Arc` and
+//! `Weak` require the `alloc` feature, which is implied by the default `std`
+//! feature.
 
 #![no_std]
 
-#[cfg(feature = "std")]
+#[cfg(any(feature = "std", test))]
 extern crate std;
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "derive")]
+extern crate same_derive;
+
+use core::cmp::Ordering;
+use core::fmt;
 use core::hash::{Hash, Hasher};
+use core::mem;
+use core::ops::Deref;
+use core::pin::Pin;
+
+#[macro_use]
+mod assert;
+mod composite;
+#[cfg(feature = "alloc")]
+mod cross;
+#[cfg(feature = "std")]
+pub mod codec;
+#[cfg(feature = "std")]
+pub mod deep;
+pub mod ext;
+pub mod hasher;
+pub mod id;
+#[cfg(feature = "std")]
+pub mod index_map;
+#[cfg(feature = "std")]
+pub mod index_set;
+#[cfg(feature = "std")]
+pub mod intern;
+#[cfg(feature = "std")]
+pub mod map;
+pub mod pin;
+mod ptr;
+pub mod region;
+#[cfg(feature = "std")]
+pub mod set;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(feature = "std")]
+pub mod visit;
+#[cfg(feature = "std")]
+pub mod weak;
+
+#[cfg(feature = "derive")]
+pub use same_derive::{RefHash, Same};
+pub use hasher::{IdentityBuildHasher, IdentityHasher};
+pub use id::ObjectId;
+pub use pin::PinnedId;
+
+/// Items used by the macros, not public API.
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "derive")]
+    pub use core::hash::Hasher;
+    pub use assert::{assert_failed, Details, Fallback, Probe, ViaDebug, ViaEq, ViaPointer, ViaWeak};
+}
+#[cfg(feature = "std")]
+pub use index_map::IdentityIndexMap;
+#[cfg(feature = "std")]
+pub use index_set::IdentityIndexSet;
+#[cfg(feature = "std")]
+pub use map::IdentityMap;
+#[cfg(feature = "std")]
+pub use set::IdentitySet;
+
+/// Returns the address of the pointee, discarding pointer metadata.
+fn addr<T: ?Sized>(ptr: *const T) -> usize {
+    ptr.cast::<()>() as usize
+}
+
+/// Formats the address like `{:p}` does.
+fn fmt_addr(address: usize, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Pointer::fmt(&(address as *const ()), f)
+}
+
+/// Formats the addresses a key points to, used by `Debug` of `RefCmp`.
+///
+/// This is implemented for all pointers and composite keys supported by this
+/// crate. Composite keys print the addresses of their parts, for example
+/// `(0x5581a4e0, None)`.
+#[doc(hidden)]
+pub trait FmtAddress {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result;
+}
+
+/// Formats the addresses of the key using `Debug`.
+struct AddressOf<'a, T: 'a + ?Sized>(&'a T);
+
+impl<'a, T: FmtAddress + ?Sized> fmt::Debug for AddressOf<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt_address(f)
+    }
+}
 
 /// Allows to test identity of objects.
 ///
@@ -39,75 +191,284 @@ use core::hash::{Hash, Hasher};
 /// ```
 ///
 /// This trait is currently implemented for *shared* references,
-/// `Rc` and `Arc`.
+/// `Rc`, `Arc`, their `Weak` counterparts and pinned shared pointers.
+/// It's also implemented for raw pointers, `NonNull` and function pointers,
+/// which are compared by address. Raw pointers to sized types, slices and
+/// `str` behave exactly like `&T`; pointers to trait objects are not
+/// supported.
+///
+/// Tuples, arrays, slices, `Vec` and `Option` are the same if all their
+/// elements are the same, so for example `RefCmp<(Rc<A>, Rc<B>)>` can be
+/// used as a key for an edge between two objects.
+///
+/// A `Weak` is the same as another `Weak` if both point to the same
+/// allocation, even if the value was already dropped. (The allocation
+/// itself is kept alive by the `Weak`, so its address can't be reused
+/// in the meantime.) All dangling handles created by `Weak::new()` are the
+/// same as each other. `RefCmp<Weak<T>>` can also be compared with
+/// `RefCmp<Rc<T>>` (or `Arc` respectively) without upgrading the `Weak`.
+///
+/// The pointee doesn't have to be `Sized`. Two slices are the same only if
+/// they start at the same address *and* have the same length. Two trait
+/// objects are the same if they point to the same address, regardless of
+/// their vtables. (The compiler may emit several copies of the same vtable,
+/// so comparing them would give spurious `false` results.) If you need
+/// to take vtables into account, use `StrictRefCmp`.
+///
+/// Formally, two references are the same if they have the same address
+/// and their pointees have the same size. As a consequence, slices of
+/// zero-sized types can't be distinguished by their length.
 ///
 /// Note that it doesn't make sense to implement this trait for mutable
 /// references, nor boxes because there can never be two of them pointing
 /// to the same address, so the implementation would always return `false`.
+/// If they are pinned, their address is stable though, so it can be saved
+/// as a `PinnedId` and compared later.
 pub trait Same {
     /// Returns true if `self` is the same instance of object as `other`.
     fn same(&self, other: &Self) -> bool;
 }
 
+/// Allows to test identity of objects held by different kinds of pointers.
+///
+/// This trait relates to `Same` like `PartialEq<Rhs>` relates to `Eq`. It's
+/// implemented for all types implementing `Same` (with `Rhs = Self`) and
+/// with the `alloc` feature also for all combinations of `&T`, `Rc<T>`,
+/// `Arc<T>` and their `Weak` counterparts.
+///
+/// Two strong pointers (`&T`, `Rc<T>` or `Arc<T>`) are the same if they
+/// would be the same as references. When one of the pointers is a `Weak`,
+/// only addresses are compared, just like when comparing two `Weak`s.
+///
+/// # Example
+///
+/// ```
+/// use same::SameAs;
+/// use std::rc::Rc;
+///
+/// let node = Rc::new(42);
+/// let node_ref: &i32 = &node;
+/// let weak = Rc::downgrade(&node);
+///
+/// assert!(node.same_as(&node_ref));
+/// assert!(node_ref.same_as(&weak));
+/// assert!(!node.same_as(&&42));
+/// ```
+pub trait SameAs<Rhs: ?Sized = Self> {
+    /// Returns true if `self` points to the same instance of object as
+    /// `other`.
+    fn same_as(&self, other: &Rhs) -> bool;
+}
+
+impl<T: Same + ?Sized> SameAs<T> for T {
+    fn same_as(&self, other: &T) -> bool {
+        self.same(other)
+    }
+}
+
 /// Hashes the pointer to the object instead of the object itself.
 ///
 /// This trait works exatly like `Hash`, the only difference being
-/// that it hashes the pointer.
+/// that it hashes the pointer. Only the address is hashed, pointer
+/// metadata (length or vtable) is ignored.
 pub trait RefHash {
     /// Feeds the value into the hasher.
     fn ref_hash<H: Hasher>(&self, hasher: &mut H);
 }
 
-impl<'a, T> Same for &'a T {
+/// Orders objects by their addresses.
+///
+/// This trait works like `Ord`, the only difference being that it compares
+/// pointers. It's consistent with `Same`: two values are ordered as `Equal`
+/// if and only if they are the same. Objects that have the same address
+/// but different sizes (e.g. slices of different lengths starting at the
+/// same address) are ordered by their size.
+///
+/// The order is *not* stable across different runs of the program (not
+/// even across different builds or different inputs), since it depends on
+/// where the objects were allocated. It's only guaranteed to stay the same
+/// for two objects as long as both of them are alive. Thus it's suitable
+/// for storing objects in sorted data structures such as `BTreeSet`, but
+/// the iteration order of such structures should not be relied upon.
+pub trait RefOrd {
+    /// Returns the ordering of `self` relative to `other`.
+    fn ref_cmp(&self, other: &Self) -> Ordering;
+}
+
+impl<T: ?Sized> Same for &T {
     fn same(&self, other: &Self) -> bool {
-        let a: *const T = *self;
-        let b: *const T = *other;
-        
-        a == b
+        addr(*self) == addr(*other) && mem::size_of_val(*self) == mem::size_of_val(*other)
     }
 }
 
-#[cfg(feature = "std")]
-impl<T> Same for std::rc::Rc<T> {
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> Same for alloc::rc::Rc<T> {
     fn same(&self, other: &Self) -> bool {
-        std::rc::Rc::ptr_eq(self, other)
+        (&**self).same(&&**other)
     }
 }
 
-#[cfg(feature = "std")]
-impl<T> Same for std::sync::Arc<T> {
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> Same for alloc::sync::Arc<T> {
+    fn same(&self, other: &Self) -> bool {
+        (&**self).same(&&**other)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> Same for alloc::rc::Weak<T> {
+    fn same(&self, other: &Self) -> bool {
+        addr(self.as_ptr()) == addr(other.as_ptr())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> Same for alloc::sync::Weak<T> {
+    fn same(&self, other: &Self) -> bool {
+        addr(self.as_ptr()) == addr(other.as_ptr())
+    }
+}
+
+/// Pinned pointers are the same if the pointers themselves are the same.
+///
+/// This covers `Pin<&T>`, `Pin<Rc<T>>` and `Pin<Arc<T>>`. Pinned unique
+/// pointers are not covered, use `PinnedId` for them.
+impl<P: Deref + Same> Same for Pin<P> {
     fn same(&self, other: &Self) -> bool {
-        std::sync::Arc::ptr_eq(self, other)
+        (&**self).same(&&**other)
     }
 }
 
-impl<'a, T> RefHash for &'a T {
+impl<T: ?Sized> RefHash for &T {
     fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
-        let ptr: *const T = *self;
+        addr(*self).hash(hasher);
+    }
+}
 
-        ptr.hash(hasher);
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> RefHash for alloc::rc::Rc<T> {
+    fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+        (&**self).ref_hash(hasher);
     }
 }
 
-#[cfg(feature = "std")]
-impl<T> RefHash for std::rc::Rc<T> {
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> RefHash for alloc::sync::Arc<T> {
     fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
         (&**self).ref_hash(hasher);
     }
 }
 
-#[cfg(feature = "std")]
-impl<T> RefHash for std::sync::Arc<T> {
+/// Hashes the address of the allocation, so the hash is equal to the hash of
+/// `Rc` pointing to the same allocation.
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> RefHash for alloc::rc::Weak<T> {
+    fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+        addr(self.as_ptr()).hash(hasher);
+    }
+}
+
+/// Hashes the address of the allocation, so the hash is equal to the hash of
+/// `Arc` pointing to the same allocation.
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> RefHash for alloc::sync::Weak<T> {
+    fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+        addr(self.as_ptr()).hash(hasher);
+    }
+}
+
+impl<P: Deref + Same> RefHash for Pin<P> {
     fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
         (&**self).ref_hash(hasher);
     }
 }
 
+impl<T: ?Sized> RefOrd for &T {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        addr(*self).cmp(&addr(*other))
+            .then_with(|| mem::size_of_val(*self).cmp(&mem::size_of_val(*other)))
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> RefOrd for alloc::rc::Rc<T> {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        (&**self).ref_cmp(&&**other)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> RefOrd for alloc::sync::Arc<T> {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        (&**self).ref_cmp(&&**other)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> RefOrd for alloc::rc::Weak<T> {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        addr(self.as_ptr()).cmp(&addr(other.as_ptr()))
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> RefOrd for alloc::sync::Weak<T> {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        addr(self.as_ptr()).cmp(&addr(other.as_ptr()))
+    }
+}
+
+impl<P: Deref + Same> RefOrd for Pin<P> {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        (&**self).ref_cmp(&&**other)
+    }
+}
+
+impl<T: ?Sized> FmtAddress for &T {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_addr(addr(*self), f)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> FmtAddress for alloc::rc::Rc<T> {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_addr(addr(alloc::rc::Rc::as_ptr(self)), f)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> FmtAddress for alloc::sync::Arc<T> {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_addr(addr(alloc::sync::Arc::as_ptr(self)), f)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> FmtAddress for alloc::rc::Weak<T> {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_addr(addr(self.as_ptr()), f)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> FmtAddress for alloc::sync::Weak<T> {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_addr(addr(self.as_ptr()), f)
+    }
+}
+
+impl<P: Deref> FmtAddress for Pin<P> {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_addr(addr(&**self), f)
+    }
+}
+
 /// Wrapper for types to make their equality operations compare pointers.
 ///
-/// This wrapper turns `Same` into `PartialEq` and `RefHash` into `Hash`.
-/// It is mainly useful for storing unique objects in hash sets and similar
-/// data structures.
+/// This wrapper turns `Same` into `PartialEq`, `RefHash` into `Hash` and
+/// `RefOrd` into `Ord`. It is mainly useful for storing unique objects in
+/// hash sets, `BTreeSet`s and similar data structures.
 ///
 /// # Example
 /// ```
@@ -127,7 +488,132 @@ impl<T> RefHash for std::sync::Arc<T> {
 /// // but `b` doesn't, even though it has same value.
 /// assert!(hash_set.insert(RefCmp(b)));
 /// ```
-pub struct RefCmp<T: Same>(pub T);
+///
+/// The struct itself has no bounds, so it can be used in generic code; the
+/// comparison traits are implemented when `T` supports them. It has the same
+/// layout as `T`, so references, slices, vectors and boxed slices of values
+/// can be converted to the ones of wrappers (and back) without copying, see
+/// `from_ref`, `from_slice`, `from_vec` and `from_boxed_slice`. This isn't
+/// possible for hash maps and sets, since the wrapper hashes differently.
+#[derive(Clone, Copy, Default)]
+#[repr(transparent)]
+pub struct RefCmp<T>(pub T);
+
+impl<T> RefCmp<T> {
+    /// Wraps the value.
+    pub fn new(value: T) -> Self {
+        RefCmp(value)
+    }
+
+    /// Unwraps the value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+
+    /// Views a reference to the value as a reference to the wrapper.
+    pub fn from_ref(value: &T) -> &Self {
+        // SAFETY: `RefCmp` is `repr(transparent)`.
+        unsafe { &*(value as *const T as *const Self) }
+    }
+
+    /// Views a mutable reference to the value as a mutable reference to the
+    /// wrapper.
+    pub fn from_mut(value: &mut T) -> &mut Self {
+        // SAFETY: `RefCmp` is `repr(transparent)`.
+        unsafe { &mut *(value as *mut T as *mut Self) }
+    }
+
+    /// Views a slice of values as a slice of wrappers.
+    ///
+    /// This allows passing for example `&[Rc<T>]` to APIs expecting identity
+    /// semantics without copying it.
+    pub fn from_slice(slice: &[T]) -> &[Self] {
+        // SAFETY: `RefCmp` is `repr(transparent)`, so the slices have the
+        // same layout.
+        unsafe { &*(slice as *const [T] as *const [Self]) }
+    }
+
+    /// Views a mutable slice of values as a mutable slice of wrappers.
+    pub fn from_mut_slice(slice: &mut [T]) -> &mut [Self] {
+        // SAFETY: `RefCmp` is `repr(transparent)`, so the slices have the
+        // same layout.
+        unsafe { &mut *(slice as *mut [T] as *mut [Self]) }
+    }
+
+    /// Views a slice of wrappers as a slice of the values.
+    pub fn as_inner_slice(slice: &[Self]) -> &[T] {
+        // SAFETY: `RefCmp` is `repr(transparent)`, so the slices have the
+        // same layout.
+        unsafe { &*(slice as *const [Self] as *const [T]) }
+    }
+
+    /// Views a mutable slice of wrappers as a mutable slice of the values.
+    pub fn as_inner_mut_slice(slice: &mut [Self]) -> &mut [T] {
+        // SAFETY: `RefCmp` is `repr(transparent)`, so the slices have the
+        // same layout.
+        unsafe { &mut *(slice as *mut [Self] as *mut [T]) }
+    }
+
+    /// Converts a vector of values into a vector of wrappers without
+    /// reallocating.
+    #[cfg(feature = "alloc")]
+    pub fn from_vec(vec: alloc::vec::Vec<T>) -> alloc::vec::Vec<Self> {
+        let mut vec = mem::ManuallyDrop::new(vec);
+        // SAFETY: `RefCmp` is `repr(transparent)`, so it has the same size
+        // and alignment as `T` and the allocation can be reused as is.
+        unsafe { alloc::vec::Vec::from_raw_parts(vec.as_mut_ptr() as *mut Self, vec.len(), vec.capacity()) }
+    }
+
+    /// Converts a vector of wrappers into a vector of the values without
+    /// reallocating.
+    #[cfg(feature = "alloc")]
+    pub fn into_inner_vec(vec: alloc::vec::Vec<Self>) -> alloc::vec::Vec<T> {
+        let mut vec = mem::ManuallyDrop::new(vec);
+        // SAFETY: `RefCmp` is `repr(transparent)`, so it has the same size
+        // and alignment as `T` and the allocation can be reused as is.
+        unsafe { alloc::vec::Vec::from_raw_parts(vec.as_mut_ptr() as *mut T, vec.len(), vec.capacity()) }
+    }
+
+    /// Converts a boxed slice of values into a boxed slice of wrappers
+    /// without reallocating.
+    #[cfg(feature = "alloc")]
+    pub fn from_boxed_slice(slice: alloc::boxed::Box<[T]>) -> alloc::boxed::Box<[Self]> {
+        // SAFETY: `RefCmp` is `repr(transparent)`, so the slices have the
+        // same layout.
+        unsafe { alloc::boxed::Box::from_raw(alloc::boxed::Box::into_raw(slice) as *mut [Self]) }
+    }
+
+    /// Converts a boxed slice of wrappers into a boxed slice of the values
+    /// without reallocating.
+    #[cfg(feature = "alloc")]
+    pub fn into_inner_boxed_slice(slice: alloc::boxed::Box<[Self]>) -> alloc::boxed::Box<[T]> {
+        // SAFETY: `RefCmp` is `repr(transparent)`, so the slices have the
+        // same layout.
+        unsafe { alloc::boxed::Box::from_raw(alloc::boxed::Box::into_raw(slice) as *mut [T]) }
+    }
+}
+
+impl<T> From<T> for RefCmp<T> {
+    fn from(value: T) -> Self {
+        RefCmp(value)
+    }
+}
+
+/// Prints the address and the value, for example `RefCmp(0x5581a4e0, 42)`.
+///
+/// Composite keys print the addresses of their parts, for example
+/// `RefCmp((0x5581a4e0, 0x5581a500), (1, 2))`.
+impl<T: fmt::Debug + FmtAddress> fmt::Debug for RefCmp<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("RefCmp").field(&AddressOf(&self.0)).field(&self.0).finish()
+    }
+}
+
+impl<T: fmt::Pointer> fmt::Pointer for RefCmp<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Pointer::fmt(&self.0, f)
+    }
+}
 
 impl<T: Same> Eq for RefCmp<T> {}
 impl<T: Same> PartialEq for RefCmp<T> {
@@ -142,7 +628,19 @@ impl<T: Same + RefHash> Hash for RefCmp<T> {
     }
 }
 
-impl<T: Same> core::ops::Deref for RefCmp<T> {
+impl<T: Same + RefOrd> PartialOrd for RefCmp<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Same + RefOrd> Ord for RefCmp<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.ref_cmp(&other.0)
+    }
+}
+
+impl<T> Deref for RefCmp<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -150,18 +648,265 @@ impl<T: Same> core::ops::Deref for RefCmp<T> {
     }
 }
 
-impl<U, T: Same + AsRef<U>> AsRef<U> for RefCmp<T> {
+impl<U, T: AsRef<U>> AsRef<U> for RefCmp<T> {
     fn as_ref(&self) -> &U {
         self.0.as_ref()
     }
 }
 
-impl<T: Same> core::borrow::Borrow<T> for RefCmp<T> {
-    fn borrow(&self) -> &T {
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> PartialEq<RefCmp<alloc::rc::Weak<T>>> for RefCmp<alloc::rc::Rc<T>> {
+    fn eq(&self, other: &RefCmp<alloc::rc::Weak<T>>) -> bool {
+        addr(alloc::rc::Rc::as_ptr(&self.0)) == addr(other.0.as_ptr())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> PartialEq<RefCmp<alloc::rc::Rc<T>>> for RefCmp<alloc::rc::Weak<T>> {
+    fn eq(&self, other: &RefCmp<alloc::rc::Rc<T>>) -> bool {
+        other == self
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> PartialEq<RefCmp<alloc::sync::Weak<T>>> for RefCmp<alloc::sync::Arc<T>> {
+    fn eq(&self, other: &RefCmp<alloc::sync::Weak<T>>) -> bool {
+        addr(alloc::sync::Arc::as_ptr(&self.0)) == addr(other.0.as_ptr())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> PartialEq<RefCmp<alloc::sync::Arc<T>>> for RefCmp<alloc::sync::Weak<T>> {
+    fn eq(&self, other: &RefCmp<alloc::sync::Arc<T>>) -> bool {
+        other == self
+    }
+}
+
+/// Wrapper for pointers to make their equality operations compare pointers
+/// including their metadata.
+///
+/// Unlike `RefCmp`, which ignores vtables, this wrapper considers two trait
+/// objects equal only if they point to the same address *and* use the same
+/// vtable, that is if they are the same object viewed through the same
+/// trait implementation. Note that the compiler may duplicate vtables, so
+/// two handles to the same object may compare unequal even if they were
+/// created from the same type.
+///
+/// For slices the behavior is same as `RefCmp`: both address and length
+/// are compared.
+///
+/// # Example
+/// ```
+/// use same::StrictRefCmp;
+///
+/// use std::rc::Rc;
+/// use std::fmt::Debug;
+///
+/// let a: Rc<dyn Debug> = Rc::new(42);
+/// let a_cloned = a.clone();
+/// let b: Rc<dyn Debug> = Rc::new(42);
+///
+/// assert!(StrictRefCmp(a.clone()) == StrictRefCmp(a_cloned));
+/// assert!(StrictRefCmp(a) != StrictRefCmp(b));
+/// ```
+pub struct StrictRefCmp<T: Deref>(pub T);
+
+impl<T: Deref> Eq for StrictRefCmp<T> {}
+impl<T: Deref> PartialEq for StrictRefCmp<T> {
+    fn eq(&self, other: &Self) -> bool {
+        core::ptr::eq(&*self.0, &*other.0)
+    }
+}
+
+impl<T: Deref> Hash for StrictRefCmp<T> {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        let ptr: *const T::Target = &*self.0;
+
+        ptr.hash(hasher);
+    }
+}
+
+impl<T: Deref> Deref for StrictRefCmp<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
 
+/// Borrowed form of identity keys, comparing and hashing the address of the
+/// object.
+///
+/// A reference to `RefKey<T>` has the same address (and size) as the
+/// reference to `T` it was created from. `RefCmp<&T>`, `RefCmp<Rc<T>>`,
+/// `RefCmp<Arc<T>>` and `RefCmp<Pin<P>>` implement `Borrow<RefKey<T>>`, so
+/// sets and maps keyed by them can be queried using plain references or
+/// handles without cloning them.
+///
+/// # Example
+///
+/// ```
+/// use same::{RefCmp, RefKey};
+/// use std::collections::HashSet;
+/// use std::rc::Rc;
+///
+/// let a = Rc::new(42);
+/// let mut set = HashSet::new();
+/// set.insert(RefCmp(a.clone()));
+///
+/// assert!(set.contains(RefKey::of(&a)));
+/// assert!(set.contains(RefKey::new(&*a)));
+/// assert!(!set.contains(RefKey::of(&Rc::new(42))));
+/// ```
+///
+/// `RefCmp<T>` doesn't implement `Borrow<T>`, because `T` itself hashes the
+/// value rather than the address, which would make lookups silently fail:
+///
+/// ```compile_fail
+/// use same::RefCmp;
+/// use std::collections::HashSet;
+/// use std::rc::Rc;
+///
+/// let a = Rc::new(42);
+/// let mut set = HashSet::new();
+/// set.insert(RefCmp(a.clone()));
+/// set.contains(&a);
+/// ```
+#[repr(transparent)]
+pub struct RefKey<T: ?Sized>(T);
+
+impl<T: ?Sized> RefKey<T> {
+    /// Wraps the reference.
+    pub fn new(target: &T) -> &Self {
+        // SAFETY: `RefKey` is `repr(transparent)`, so it has the same layout
+        // and pointer metadata as `T`.
+        unsafe { &*(target as *const T as *const Self) }
+    }
+
+    /// Wraps the reference to the object the handle points to.
+    pub fn of<P: Deref<Target = T>>(handle: &P) -> &Self {
+        Self::new(handle)
+    }
+}
+
+impl<T: ?Sized> Eq for RefKey<T> {}
+
+impl<T: ?Sized> PartialEq for RefKey<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (&self.0).same(&&other.0)
+    }
+}
+
+impl<T: ?Sized> Hash for RefKey<T> {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        (&self.0).ref_hash(hasher);
+    }
+}
+
+impl<T: ?Sized> core::borrow::Borrow<RefKey<T>> for RefCmp<&T> {
+    fn borrow(&self) -> &RefKey<T> {
+        RefKey::new(self.0)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> core::borrow::Borrow<RefKey<T>> for RefCmp<alloc::rc::Rc<T>> {
+    fn borrow(&self) -> &RefKey<T> {
+        RefKey::of(&self.0)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized> core::borrow::Borrow<RefKey<T>> for RefCmp<alloc::sync::Arc<T>> {
+    fn borrow(&self) -> &RefKey<T> {
+        RefKey::of(&self.0)
+    }
+}
+
+impl<P: Deref + Same> core::borrow::Borrow<RefKey<P::Target>> for RefCmp<Pin<P>> {
+    fn borrow(&self) -> &RefKey<P::Target> {
+        RefKey::of(&self.0)
+    }
+}
+
+/// Values which can be used to look up keys of type `K` in identity
+/// collections.
+///
+/// Any key can be looked up by a reference to a key of the same type, for
+/// example `&Weak<T>` or `&(Rc<A>, Rc<B>)`. Keys pointing to an object (`&T`,
+/// `Rc<T>`, `Arc<T>` and pinned versions of them) can also be looked up by a
+/// plain reference to the object, so there's no need to clone a handle just to
+/// perform a lookup.
+///
+/// Identity collections accept any `Lookup` value in their lookup methods, so
+/// for example `map.get(&rc)` and `map.get(&*rc)` both work. The lookup is
+/// performed using a borrowed form of `RefCmp<K>`, so this works with
+/// `HashMap<RefCmp<K>, V>` as well.
+///
+/// # Example
+///
+/// ```
+/// use same::{Lookup, RefCmp};
+/// use std::collections::HashSet;
+/// use std::rc::{Rc, Weak};
+///
+/// let a = Rc::new(42);
+/// let mut set = HashSet::new();
+/// set.insert(RefCmp(a.clone()));
+///
+/// assert!(set.contains(Lookup::<Rc<i32>>::lookup_key(&a)));
+/// assert!(set.contains(Lookup::<Rc<i32>>::lookup_key(&*a)));
+///
+/// let weak = Rc::downgrade(&a);
+/// let mut weaks = HashSet::new();
+/// weaks.insert(RefCmp(weak.clone()));
+/// assert!(weaks.contains(Lookup::<Weak<i32>>::lookup_key(&weak)));
+/// ```
+pub trait Lookup<K> {
+    /// The borrowed form of `RefCmp<K>` used for the lookup.
+    type Key: ?Sized + Hash + Eq;
+
+    /// Returns the borrowed key.
+    fn lookup_key(&self) -> &Self::Key;
+}
+
+impl<K: Same + RefHash> Lookup<K> for K {
+    type Key = RefCmp<K>;
+
+    fn lookup_key(&self) -> &RefCmp<K> {
+        RefCmp::from_ref(self)
+    }
+}
+
+/// Implements `Lookup` of handles by plain references to their targets.
+macro_rules! target_lookup {
+    ($($(#[$attr:meta])* [$($lt:lifetime)*] $handle:ty),* $(,)*) => {
+        $(
+            $(#[$attr])*
+            impl<$($lt,)* T: ?Sized> Lookup<$handle> for T {
+                type Key = RefKey<T>;
+
+                fn lookup_key(&self) -> &RefKey<T> {
+                    RefKey::new(self)
+                }
+            }
+        )*
+    };
+}
+
+target_lookup! {
+    ['a] &'a T,
+    ['a] Pin<&'a T>,
+    #[cfg(feature = "alloc")]
+    [] alloc::rc::Rc<T>,
+    #[cfg(feature = "alloc")]
+    [] alloc::sync::Arc<T>,
+    #[cfg(feature = "alloc")]
+    [] Pin<alloc::rc::Rc<T>>,
+    #[cfg(feature = "alloc")]
+    [] Pin<alloc::sync::Arc<T>>,
+}
+
 #[cfg(test)]
 mod tests {
     use ::Same;
@@ -186,6 +931,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "alloc")]
     fn rcs() {
         let a = ::std::rc::Rc::new(42);
         let a_cloned = a.clone();
@@ -201,6 +947,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "alloc")]
     fn arcs() {
         let a = ::std::sync::Arc::new(42);
         let a_cloned = a.clone();
@@ -214,4 +961,309 @@ mod tests {
         assert!(!hash_set.insert(RefCmp(a_cloned)));
         assert!(hash_set.insert(RefCmp(b)));
     }
+
+    #[test]
+    fn unsized_refs() {
+        let array = [1, 2, 3];
+        let slice: &[i32] = &array;
+        let whole: &[i32] = &array[..];
+        let prefix: &[i32] = &array[..2];
+
+        assert!(slice.same(&whole));
+        // Same address, different length.
+        assert!(!slice.same(&prefix));
+
+        let mut hash_set = ::std::collections::HashSet::new();
+        assert!(hash_set.insert(RefCmp(slice)));
+        assert!(!hash_set.insert(RefCmp(whole)));
+        assert!(hash_set.insert(RefCmp(prefix)));
+    }
+
+    #[test]
+    fn ordering() {
+        use ::RefOrd;
+        use std::cmp::Ordering;
+
+        let array = [1, 2, 3];
+        let whole: &[i32] = &array;
+        let prefix: &[i32] = &array[..2];
+        let suffix: &[i32] = &array[1..];
+
+        assert_eq!(whole.ref_cmp(&whole), Ordering::Equal);
+        assert_eq!(prefix.ref_cmp(&whole), Ordering::Less);
+        assert_eq!(whole.ref_cmp(&suffix), Ordering::Less);
+        assert_eq!(suffix.ref_cmp(&prefix), Ordering::Greater);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn btree_set() {
+        let a = ::std::rc::Rc::new(42);
+        let a_cloned = a.clone();
+        let b = ::std::rc::Rc::new(42);
+
+        let mut set = ::std::collections::BTreeSet::new();
+        assert!(set.insert(RefCmp(a)));
+        assert!(!set.insert(RefCmp(a_cloned.clone())));
+        assert!(set.insert(RefCmp(b)));
+        assert!(set.contains(&RefCmp(a_cloned)));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn unsized_rcs() {
+        use std::fmt::Debug;
+
+        let a: ::std::rc::Rc<str> = "foo".into();
+        let a_cloned = a.clone();
+        let b: ::std::rc::Rc<str> = "foo".into();
+
+        assert!(a.same(&a_cloned));
+        assert!(!a.same(&b));
+
+        let c: ::std::sync::Arc<dyn Debug> = ::std::sync::Arc::new(42);
+        let c_cloned = c.clone();
+        let d: ::std::sync::Arc<dyn Debug> = ::std::sync::Arc::new(42);
+
+        assert!(c.same(&c_cloned));
+        assert!(!c.same(&d));
+
+        let mut hash_set = ::std::collections::HashSet::new();
+        assert!(hash_set.insert(RefCmp(c)));
+        assert!(!hash_set.insert(RefCmp(c_cloned)));
+        assert!(hash_set.insert(RefCmp(d)));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn trait_objects() {
+        use std::fmt::Debug;
+        use ::StrictRefCmp;
+
+        #[derive(Debug)]
+        #[repr(C)]
+        struct Outer {
+            inner: i32,
+        }
+
+        let a = Outer { inner: 42 };
+        // Both point to the same address, but use the vtables of different
+        // types.
+        let a_outer: &dyn Debug = &a;
+        let a_inner: &dyn Debug = &a.inner;
+        let b = 42;
+        let b_debug: &dyn Debug = &b;
+
+        assert!(a_outer.same(&a_outer));
+        assert!(!a_outer.same(&b_debug));
+        // Same data address is enough, vtables are not compared.
+        assert!(a_outer.same(&a_inner));
+        assert_eq!(hash(&RefCmp(a_outer)), hash(&RefCmp(a_inner)));
+        assert!(StrictRefCmp(a_outer) == StrictRefCmp(a_outer));
+        assert!(StrictRefCmp(a_outer) != StrictRefCmp(a_inner));
+
+        let c: ::std::rc::Rc<dyn Debug> = ::std::rc::Rc::new(42);
+        let d: ::std::rc::Rc<dyn Debug> = ::std::rc::Rc::new(42);
+
+        let mut hash_set = ::std::collections::HashSet::new();
+        assert!(hash_set.insert(StrictRefCmp(c.clone())));
+        assert!(!hash_set.insert(StrictRefCmp(c)));
+        assert!(hash_set.insert(StrictRefCmp(d)));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn weaks() {
+        use std::rc::{Rc, Weak};
+
+        let a = Rc::new(42);
+        let a_weak = Rc::downgrade(&a);
+        let a_weak_again = Rc::downgrade(&a);
+        let b = Rc::new(42);
+        let b_weak = Rc::downgrade(&b);
+
+        assert!(a_weak.same(&a_weak_again));
+        assert!(!a_weak.same(&b_weak));
+        assert!(Weak::<i32>::new().same(&Weak::new()));
+        assert!(!a_weak.same(&Weak::new()));
+
+        assert!(RefCmp(a.clone()) == RefCmp(a_weak.clone()));
+        assert!(RefCmp(a_weak.clone()) == RefCmp(a.clone()));
+        assert!(RefCmp(b.clone()) != RefCmp(a_weak.clone()));
+        assert!(RefCmp(a.clone()) != RefCmp(Weak::new()));
+        assert_eq!(hash(&RefCmp(a.clone())), hash(&RefCmp(a_weak.clone())));
+
+        let mut hash_set = ::std::collections::HashSet::new();
+        assert!(hash_set.insert(RefCmp(a_weak)));
+        assert!(!hash_set.insert(RefCmp(a_weak_again.clone())));
+        assert!(hash_set.insert(RefCmp(b_weak)));
+
+        // The value is gone but the identity of the allocation remains.
+        drop(a);
+        assert!(!hash_set.insert(RefCmp(a_weak_again)));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn sync_weaks() {
+        use std::sync::{Arc, Weak};
+
+        let a = Arc::new(42);
+        let a_weak = Arc::downgrade(&a);
+        let b = Arc::new(42);
+        let b_weak = Arc::downgrade(&b);
+
+        assert!(a_weak.same(&Arc::downgrade(&a)));
+        assert!(!a_weak.same(&b_weak));
+        assert!(Weak::<i32>::new().same(&Weak::new()));
+
+        assert!(RefCmp(a.clone()) == RefCmp(a_weak.clone()));
+        assert!(RefCmp(b_weak.clone()) != RefCmp(a.clone()));
+        assert_eq!(hash(&RefCmp(b.clone())), hash(&RefCmp(b_weak)));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn pins() {
+        use std::pin::Pin;
+        use std::rc::Rc;
+
+        let a = Rc::pin(42);
+        let a_cloned = a.clone();
+        let b = Rc::pin(42);
+
+        assert!(a.same(&a_cloned));
+        assert!(!a.same(&b));
+
+        let value = 42;
+        assert!(Pin::new(&value).same(&Pin::new(&value)));
+
+        let mut hash_set = ::std::collections::HashSet::new();
+        assert!(hash_set.insert(RefCmp(a)));
+        assert!(!hash_set.insert(RefCmp(a_cloned)));
+        assert!(hash_set.insert(RefCmp(b)));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn wrapper_api() {
+        use std::format;
+        use std::rc::{Rc, Weak};
+
+        let a = RefCmp::new(Rc::new(42));
+        let a_cloned = a.clone();
+        assert!(a == a_cloned);
+        assert_eq!(format!("{:?}", a), format!("RefCmp({:p}, 42)", a.0));
+        assert_eq!(format!("{:p}", a), format!("{:p}", a.0));
+        assert_eq!(*a.into_inner(), 42);
+
+        let value = 1;
+        let reference = RefCmp::from(&value);
+        let copy = reference;
+        assert!(reference == copy);
+
+        let dangling = RefCmp::<Weak<i32>>::default();
+        assert!(dangling == RefCmp(Weak::new()));
+        let weak = Rc::downgrade(&a_cloned);
+        assert_eq!(format!("{:?}", RefCmp(weak)), format!("RefCmp({:p}, (Weak))", a_cloned.0));
+        let pair = RefCmp((a_cloned.0.clone(), None::<Rc<i32>>));
+        assert_eq!(format!("{:?}", pair), format!("RefCmp(({:p}, None), (42, None))", a_cloned.0));
+        assert_eq!(format!("{:?}", RefCmp([&value])), format!("RefCmp([{:p}], [1])", &value));
+        assert_eq!(::core::mem::size_of::<RefCmp<Rc<i32>>>(), ::core::mem::size_of::<Rc<i32>>());
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn borrowed_lookup() {
+        use std::collections::{HashMap, HashSet};
+        use std::rc::Rc;
+        use std::sync::Arc;
+        use RefKey;
+
+        let a = Rc::new(42);
+        let b = Rc::new(42);
+
+        // `Rc` hashes the value, not the address, so it can't be used as the
+        // borrowed form of `RefCmp<Rc<T>>`. This used to be allowed by
+        // `Borrow<T>` and made lookups of present keys fail.
+        assert_ne!(hash(&RefCmp(a.clone())), hash(&a));
+        assert_eq!(hash(&RefCmp(a.clone())), hash(RefKey::of(&a)));
+
+        let mut set = HashSet::new();
+        set.insert(RefCmp(a.clone()));
+        assert!(set.contains(RefKey::of(&a)));
+        assert!(set.contains(RefKey::new(&*a)));
+        assert!(!set.contains(RefKey::of(&b)));
+        assert!(set.remove(RefKey::of(&a.clone())));
+
+        let c = Arc::new(1);
+        let mut map = HashMap::new();
+        map.insert(RefCmp(c.clone()), "c");
+        assert_eq!(map.get(RefKey::of(&c)), Some(&"c"));
+
+        let values = [1, 2];
+        let mut refs = HashSet::new();
+        refs.insert(RefCmp(&values[..]));
+        assert!(refs.contains(RefKey::new(&values[..])));
+        assert!(!refs.contains(RefKey::new(&values[..1])));
+
+        let pinned = Rc::pin(1);
+        let mut pins = HashSet::new();
+        pins.insert(RefCmp(pinned.clone()));
+        assert!(pins.contains(RefKey::of(&pinned)));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn views() {
+        use std::boxed::Box;
+        use std::collections::HashSet;
+        use std::rc::Rc;
+        use std::vec::Vec;
+
+        let a = Rc::new(1);
+        let b = Rc::new(1);
+        let mut handles = [a.clone(), b.clone(), a.clone()].to_vec();
+
+        let wrapped = RefCmp::from_slice(&handles);
+        assert!(wrapped[0] == wrapped[2] && wrapped[0] != wrapped[1]);
+        assert_eq!(wrapped.iter().collect::<HashSet<_>>().len(), 2);
+        assert!(RefCmp::as_inner_slice(wrapped).as_ptr() == handles.as_ptr());
+        assert!(*RefCmp::from_ref(&a) == RefCmp(a.clone()));
+
+        RefCmp::from_mut_slice(&mut handles)[1] = RefCmp(b.clone());
+        RefCmp::as_inner_mut_slice(RefCmp::from_mut_slice(&mut handles))[2] = a.clone();
+        *RefCmp::from_mut(&mut handles[0]) = RefCmp(b.clone());
+        assert!(handles[0].same(&b) && handles[1].same(&b) && handles[2].same(&a));
+
+        let pointer = handles.as_ptr() as *const RefCmp<Rc<i32>>;
+        let mut wrapped: Vec<_> = RefCmp::from_vec(handles);
+        assert!(wrapped.as_ptr() == pointer);
+        wrapped.dedup();
+        assert_eq!(wrapped.len(), 2);
+        let handles = RefCmp::into_inner_vec(wrapped);
+        assert!(handles[0].same(&b));
+
+        let boxed: Box<[_]> = handles.into_boxed_slice();
+        let pointer = boxed.as_ptr() as *const RefCmp<Rc<i32>>;
+        let wrapped = RefCmp::from_boxed_slice(boxed);
+        assert!(wrapped.as_ptr() == pointer);
+        assert!(RefCmp::into_inner_boxed_slice(wrapped)[1].same(&a));
+        assert_eq!(Rc::strong_count(&a), 1);
+    }
+
+    /// `RefCmp` can be used without repeating the bound.
+    #[allow(dead_code)]
+    struct Wrapper<T> {
+        items: std::vec::Vec<RefCmp<T>>,
+    }
+
+    #[cfg(feature = "alloc")]
+    fn hash<T: ::std::hash::Hash>(value: &T) -> u64 {
+        use std::hash::Hasher;
+
+        let mut hasher = ::std::collections::hash_map::DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
 }
@@ -0,0 +1,645 @@
+//! Hash map keyed by identity of objects.
+//!
+//! See `IdentityMap` for more information.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::BuildHasher;
+use core::iter::FromIterator;
+use core::ops::Index;
+use std::collections::hash_map::{self, HashMap, RandomState};
+
+use {Lookup, RefCmp, RefHash, Same};
+
+/// Hash map comparing keys by identity of the objects they point to.
+///
+/// This is a more convenient equivalent of `HashMap<RefCmp<K>, V>`. The keys
+/// can be any type implementing `Same` and `RefHash`, such as handles (`&T`,
+/// `Rc<T>`, `Arc<T>`, `Weak<T>`...) or tuples of them, and two keys are
+/// considered equal if they are the same. The map can be looked up by a
+/// reference to a key and handles can also be looked up by a plain reference
+/// to the object (see `Lookup`), so there's no need to clone an `Rc` just to
+/// perform a lookup. Iteration yields the original keys.
+///
+/// # Example
+///
+/// ```
+/// use same::IdentityMap;
+/// use std::rc::Rc;
+///
+/// let a = Rc::new(42);
+/// let b = Rc::new(42);
+///
+/// let mut map = IdentityMap::new();
+/// map.insert(a.clone(), "a");
+/// map.insert(b.clone(), "b");
+///
+/// assert_eq!(map.get(&a), Some(&"a"));
+/// assert_eq!(map.get(&*b), Some(&"b"));
+/// // Equal value, but different object.
+/// assert_eq!(map.get(&42), None);
+/// ```
+pub struct IdentityMap<K, V, S = RandomState> {
+    map: HashMap<RefCmp<K>, V, S>,
+}
+
+impl<K: Same + RefHash, V> IdentityMap<K, V, RandomState> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        IdentityMap { map: HashMap::new() }
+    }
+
+    /// Creates an empty map with at least the specified capacity.
+    pub fn with_capacity(capacity: usize) -> Self {
+        IdentityMap { map: HashMap::with_capacity(capacity) }
+    }
+}
+
+impl<K, V, S> IdentityMap<K, V, S> {
+    /// Creates an empty map which will use the given hash builder.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        IdentityMap { map: HashMap::with_hasher(hash_builder) }
+    }
+
+    /// Creates an empty map with at least the specified capacity, using
+    /// `hash_builder` to hash the keys.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        IdentityMap { map: HashMap::with_capacity_and_hasher(capacity, hash_builder) }
+    }
+
+    /// Returns the number of elements the map can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    /// Returns a reference to the map's `BuildHasher`.
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    /// Returns the number of elements in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the map contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all elements from the map.
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    /// Iterates over key-value pairs in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { iter: self.map.iter() }
+    }
+
+    /// Iterates over key-value pairs in arbitrary order, with mutable
+    /// references to the values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut { iter: self.map.iter_mut() }
+    }
+
+    /// Iterates over keys in arbitrary order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { iter: self.map.keys() }
+    }
+
+    /// Iterates over values in arbitrary order.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { iter: self.map.values() }
+    }
+
+    /// Iterates over mutable references to values in arbitrary order.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut { iter: self.map.values_mut() }
+    }
+
+    /// Removes all key-value pairs from the map and returns them as an
+    /// iterator, keeping the allocated memory for reuse.
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        Drain { iter: self.map.drain() }
+    }
+
+    /// Retains only the elements for which the predicate returns `true`.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        self.map.retain(|key, value| f(&key.0, value))
+    }
+}
+
+impl<K: Same + RefHash, V, S: BuildHasher> IdentityMap<K, V, S> {
+    /// Reserves capacity for at least `additional` more elements.
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional)
+    }
+
+    /// Shrinks the capacity of the map as much as possible.
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit()
+    }
+
+    /// Gets the entry for the given key for in-place manipulation.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        match self.map.entry(RefCmp(key)) {
+            hash_map::Entry::Occupied(entry) => Entry::Occupied(OccupiedEntry { entry }),
+            hash_map::Entry::Vacant(entry) => Entry::Vacant(VacantEntry { entry }),
+        }
+    }
+
+    /// Returns a reference to the value associated with the key.
+    pub fn get<Q: ?Sized + Lookup<K>>(&self, key: &Q) -> Option<&V> where RefCmp<K>: Borrow<Q::Key> {
+        self.map.get(key.lookup_key())
+    }
+
+    /// Returns the stored key and the value associated with the key.
+    pub fn get_key_value<Q: ?Sized + Lookup<K>>(&self, key: &Q) -> Option<(&K, &V)> where RefCmp<K>: Borrow<Q::Key> {
+        self.map.get_key_value(key.lookup_key()).map(|(key, value)| (&key.0, value))
+    }
+
+    /// Returns a mutable reference to the value associated with the key.
+    pub fn get_mut<Q: ?Sized + Lookup<K>>(&mut self, key: &Q) -> Option<&mut V> where RefCmp<K>: Borrow<Q::Key> {
+        self.map.get_mut(key.lookup_key())
+    }
+
+    /// Returns `true` if the map contains a key which is the same as `key`.
+    pub fn contains_key<Q: ?Sized + Lookup<K>>(&self, key: &Q) -> bool where RefCmp<K>: Borrow<Q::Key> {
+        self.map.contains_key(key.lookup_key())
+    }
+
+    /// Inserts a key-value pair into the map.
+    ///
+    /// If the map already contained a key which is the same, the value is
+    /// updated and the old value is returned. The key is not updated.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.map.insert(RefCmp(key), value)
+    }
+
+    /// Removes the key from the map, returning the associated value.
+    pub fn remove<Q: ?Sized + Lookup<K>>(&mut self, key: &Q) -> Option<V> where RefCmp<K>: Borrow<Q::Key> {
+        self.map.remove(key.lookup_key())
+    }
+
+    /// Removes the key from the map, returning the stored key and value.
+    pub fn remove_entry<Q: ?Sized + Lookup<K>>(&mut self, key: &Q) -> Option<(K, V)> where RefCmp<K>: Borrow<Q::Key> {
+        self.map.remove_entry(key.lookup_key()).map(|(key, value)| (key.0, value))
+    }
+}
+
+impl<K: Clone, V: Clone, S: Clone> Clone for IdentityMap<K, V, S> {
+    fn clone(&self) -> Self {
+        IdentityMap { map: self.map.clone() }
+    }
+}
+
+impl<K: Same + RefHash, V, S: Default> Default for IdentityMap<K, V, S> {
+    fn default() -> Self {
+        IdentityMap { map: HashMap::default() }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for IdentityMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S, Q> Index<&Q> for IdentityMap<K, V, S>
+where
+    K: Same + RefHash,
+    S: BuildHasher,
+    Q: ?Sized + Lookup<K>,
+    RefCmp<K>: Borrow<Q::Key>,
+{
+    type Output = V;
+
+    /// Returns a reference to the value associated with the key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not present in the map.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("key not found in IdentityMap")
+    }
+}
+
+impl<K: Same + RefHash, V, S: BuildHasher> Extend<(K, V)> for IdentityMap<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.map.extend(iter.into_iter().map(|(key, value)| (RefCmp(key), value)))
+    }
+}
+
+impl<K: Same + RefHash, V, S: BuildHasher + Default> FromIterator<(K, V)> for IdentityMap<K, V, S> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = IdentityMap::with_hasher(S::default());
+        map.extend(iter);
+        map
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a IdentityMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut IdentityMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, S> IntoIterator for IdentityMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { iter: self.map.into_iter() }
+    }
+}
+
+/// A view into a single entry in the map, which may either be vacant or
+/// occupied.
+///
+/// Returned by `IdentityMap::entry`.
+pub enum Entry<'a, K: 'a, V: 'a> {
+    /// The map contains a key which is the same.
+    Occupied(OccupiedEntry<'a, K, V>),
+    /// The map doesn't contain the key.
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+impl<'a, K: 'a, V: 'a> Entry<'a, K, V> {
+    /// Returns the key of this entry.
+    ///
+    /// For occupied entries this is the key already stored in the map.
+    pub fn key(&self) -> &K {
+        match *self {
+            Entry::Occupied(ref entry) => entry.key(),
+            Entry::Vacant(ref entry) => entry.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant and returns a mutable
+    /// reference to the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Inserts the result of `default` if the entry is vacant and returns
+    /// a mutable reference to the value.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Inserts the result of `default` called with the key if the entry is
+    /// vacant and returns a mutable reference to the value.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            },
+        }
+    }
+
+    /// Calls `f` with the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            },
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K: 'a, V: 'a + Default> Entry<'a, K, V> {
+    /// Inserts the default value if the entry is vacant and returns
+    /// a mutable reference to the value.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+/// An occupied entry of `IdentityMap`.
+pub struct OccupiedEntry<'a, K: 'a, V: 'a> {
+    entry: hash_map::OccupiedEntry<'a, RefCmp<K>, V>,
+}
+
+impl<'a, K: 'a, V: 'a> OccupiedEntry<'a, K, V> {
+    /// Returns the key stored in the map.
+    pub fn key(&self) -> &K {
+        &self.entry.key().0
+    }
+
+    /// Returns a reference to the value.
+    pub fn get(&self) -> &V {
+        self.entry.get()
+    }
+
+    /// Returns a mutable reference to the value.
+    pub fn get_mut(&mut self) -> &mut V {
+        self.entry.get_mut()
+    }
+
+    /// Converts the entry into a mutable reference to the value with the
+    /// lifetime of the map.
+    pub fn into_mut(self) -> &'a mut V {
+        self.entry.into_mut()
+    }
+
+    /// Replaces the value, returning the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        self.entry.insert(value)
+    }
+
+    /// Removes the entry from the map, returning the value.
+    pub fn remove(self) -> V {
+        self.entry.remove()
+    }
+
+    /// Removes the entry from the map, returning the stored key and value.
+    pub fn remove_entry(self) -> (K, V) {
+        let (key, value) = self.entry.remove_entry();
+        (key.0, value)
+    }
+}
+
+/// A vacant entry of `IdentityMap`.
+pub struct VacantEntry<'a, K: 'a, V: 'a> {
+    entry: hash_map::VacantEntry<'a, RefCmp<K>, V>,
+}
+
+impl<'a, K: 'a, V: 'a> VacantEntry<'a, K, V> {
+    /// Returns the key that would be used when inserting a value.
+    pub fn key(&self) -> &K {
+        &self.entry.key().0
+    }
+
+    /// Takes the ownership of the key.
+    pub fn into_key(self) -> K {
+        self.entry.into_key().0
+    }
+
+    /// Inserts the value into the map and returns a mutable reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.entry.insert(value)
+    }
+}
+
+/// Iterator over key-value pairs of `IdentityMap`.
+pub struct Iter<'a, K: 'a, V: 'a> {
+    iter: hash_map::Iter<'a, RefCmp<K>, V>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, value)| (&key.0, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self {
+        Iter { iter: self.iter.clone() }
+    }
+}
+
+/// Iterator over key-value pairs of `IdentityMap` with mutable values.
+pub struct IterMut<'a, K: 'a, V: 'a> {
+    iter: hash_map::IterMut<'a, RefCmp<K>, V>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, value)| (&key.0, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {}
+
+/// Iterator over keys of `IdentityMap`.
+pub struct Keys<'a, K: 'a, V: 'a> {
+    iter: hash_map::Keys<'a, RefCmp<K>, V>,
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|key| &key.0)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Keys<'a, K, V> {}
+
+impl<'a, K, V> Clone for Keys<'a, K, V> {
+    fn clone(&self) -> Self {
+        Keys { iter: self.iter.clone() }
+    }
+}
+
+/// Iterator over values of `IdentityMap`.
+pub struct Values<'a, K: 'a, V: 'a> {
+    iter: hash_map::Values<'a, RefCmp<K>, V>,
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Values<'a, K, V> {}
+
+impl<'a, K, V> Clone for Values<'a, K, V> {
+    fn clone(&self) -> Self {
+        Values { iter: self.iter.clone() }
+    }
+}
+
+/// Iterator over mutable references to values of `IdentityMap`.
+pub struct ValuesMut<'a, K: 'a, V: 'a> {
+    iter: hash_map::ValuesMut<'a, RefCmp<K>, V>,
+}
+
+impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for ValuesMut<'a, K, V> {}
+
+/// Draining iterator of `IdentityMap`.
+pub struct Drain<'a, K: 'a, V: 'a> {
+    iter: hash_map::Drain<'a, RefCmp<K>, V>,
+}
+
+impl<'a, K, V> Iterator for Drain<'a, K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, value)| (key.0, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Drain<'a, K, V> {}
+
+/// Owning iterator of `IdentityMap`.
+pub struct IntoIter<K, V> {
+    iter: hash_map::IntoIter<RefCmp<K>, V>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, value)| (key.0, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, IdentityMap};
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    #[test]
+    fn lookup() {
+        let a = Rc::new(42);
+        let b = Rc::new(42);
+        let slice: &[i32] = &[1, 2, 3];
+
+        let mut map = IdentityMap::new();
+        assert_eq!(map.insert(a.clone(), 1), None);
+        assert_eq!(map.insert(b.clone(), 2), None);
+        assert_eq!(map.insert(a.clone(), 3), Some(1));
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(map.get(&a), Some(&3));
+        assert_eq!(map[&*b], 2);
+        assert!(!map.contains_key(&42));
+        assert!(Rc::ptr_eq(map.get_key_value(&b).unwrap().0, &b));
+
+        assert_eq!(map.remove(&a), Some(3));
+        assert_eq!(map.remove(&a), None);
+        assert_eq!(Rc::strong_count(&a), 1);
+
+        let mut slices = IdentityMap::new();
+        slices.insert(slice, ());
+        assert!(slices.contains_key(slice));
+        assert!(!slices.contains_key(&slice[..2]));
+    }
+
+    #[test]
+    fn entry() {
+        let a = Rc::new("a");
+        let b = Rc::new("a");
+
+        let mut map = IdentityMap::new();
+        *map.entry(a.clone()).or_insert(0) += 1;
+        *map.entry(a.clone()).or_insert(0) += 1;
+        *map.entry(b.clone()).or_default() += 5;
+        assert_eq!(map[&a], 2);
+        assert_eq!(map[&b], 5);
+
+        match map.entry(a.clone()) {
+            Entry::Occupied(entry) => assert_eq!(entry.remove_entry().1, 2),
+            Entry::Vacant(_) => panic!("entry should be occupied"),
+        }
+        match map.entry(a.clone()) {
+            Entry::Occupied(_) => panic!("entry should be vacant"),
+            Entry::Vacant(entry) => assert!(Rc::ptr_eq(entry.key(), &a)),
+        }
+    }
+
+    #[test]
+    fn iteration_yields_handles() {
+        let a = Rc::new(1);
+        let b = Rc::new(2);
+
+        let map = [(a.clone(), "a"), (b.clone(), "b")].iter().cloned().collect::<IdentityMap<_, _>>();
+        for (key, value) in &map {
+            let expected = if *value == "a" { &a } else { &b };
+            assert!(Rc::ptr_eq(key, expected));
+        }
+
+        let mut pairs = map.into_iter().collect::<Vec<_>>();
+        pairs.sort_by_key(|pair| pair.1);
+        assert!(Rc::ptr_eq(&pairs[0].0, &a));
+        assert!(Rc::ptr_eq(&pairs[1].0, &b));
+    }
+
+    #[test]
+    fn weak_and_composite_keys() {
+        let a = Rc::new(1);
+        let b = Rc::new(2);
+
+        let mut weaks = IdentityMap::new();
+        weaks.insert(Rc::downgrade(&a), "a");
+        assert_eq!(weaks.get(&Rc::downgrade(&a)), Some(&"a"));
+        assert!(!weaks.contains_key(&Rc::downgrade(&b)));
+
+        let mut pairs = IdentityMap::new();
+        pairs.insert((a.clone(), b.clone()), "ab");
+        assert_eq!(pairs[&(a.clone(), b.clone())], "ab");
+        assert!(!pairs.contains_key(&(b.clone(), a.clone())));
+    }
+}
@@ -0,0 +1,118 @@
+//! Identity of pinned objects.
+//!
+//! See `PinnedId` for more information.
+
+use core::fmt;
+use core::ops::Deref;
+use core::pin::Pin;
+
+use id::ObjectId;
+
+/// Identity key of a pinned object.
+///
+/// Mutable references and boxes can't implement `Same`, since there can
+/// never be two of them pointing to the same object. However, once an object
+/// is pinned, it's guaranteed to stay at the same address until it's dropped,
+/// so its address can be saved and used to recognize the object later, even
+/// though no other pointer to it exists. This is useful for registering
+/// futures or other self-referential state machines in identity sets.
+///
+/// The id doesn't borrow the object, so the object can still be mutated
+/// while the id is stored elsewhere. Ids compare, hash and order the same
+/// way as `&T` does using `Same`, `RefHash` and `RefOrd`.
+///
+/// The id is only meaningful while the object is alive. After it's dropped,
+/// its address may be reused by another object, which would then have an
+/// equal id. Remove the id from any collections before dropping the object.
+///
+/// # Example
+///
+/// ```
+/// use same::PinnedId;
+///
+/// use std::collections::HashSet;
+/// use std::future::{self, Future};
+/// use std::pin::Pin;
+///
+/// let mut a: Pin<Box<dyn Future<Output = u32>>> = Box::pin(future::ready(42));
+/// let b: Pin<Box<dyn Future<Output = u32>>> = Box::pin(future::ready(42));
+///
+/// let mut registered = HashSet::new();
+/// registered.insert(PinnedId::of(&a));
+///
+/// // The id doesn't borrow the future, so it can still be polled.
+/// let _ = a.as_mut();
+/// assert!(registered.contains(&PinnedId::of(&a)));
+/// assert!(!registered.contains(&PinnedId::of(&b)));
+/// ```
+///
+/// The id is an `ObjectId` which can only be taken from pinned pointers, so
+/// it's guaranteed to identify an object that doesn't move.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct PinnedId(ObjectId);
+
+impl PinnedId {
+    /// Takes the id of the object the pinned pointer points to.
+    ///
+    /// Works for any pinned pointer, including `Pin<Box<T>>`,
+    /// `Pin<&mut T>` and pinned shared pointers.
+    pub fn of<P: Deref>(pin: &Pin<P>) -> Self {
+        PinnedId(ObjectId::of(pin))
+    }
+
+    /// Returns `true` if the pinned pointer points to the object this id was
+    /// taken from.
+    pub fn is<P: Deref>(&self, pin: &Pin<P>) -> bool {
+        *self == Self::of(pin)
+    }
+
+    /// Returns the address of the object.
+    pub fn addr(&self) -> usize {
+        self.0.addr()
+    }
+
+    /// Returns the plain id of the object.
+    pub fn id(&self) -> ObjectId {
+        self.0
+    }
+}
+
+impl fmt::Debug for PinnedId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PinnedId({:#x})", self.addr())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PinnedId;
+    use core::pin::Pin;
+
+    #[test]
+    fn pinned_mut() {
+        let mut a = 42;
+        let mut b = 42;
+        let mut a_pin = Pin::new(&mut a);
+        let b_pin = Pin::new(&mut b);
+
+        let a_id = PinnedId::of(&a_pin);
+        *a_pin = 0;
+        assert!(a_id.is(&a_pin));
+        assert!(!a_id.is(&b_pin));
+        assert_eq!(a_id, PinnedId::of(&a_pin.as_ref()));
+        assert_eq!(a_id.id(), ::id::ObjectId::of_ref(&*a_pin));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn pinned_box() {
+        use alloc::boxed::Box;
+
+        let a = Box::pin([1u8, 2, 3]);
+        let slice: Pin<&[u8]> = Pin::new(&a[..2]);
+
+        assert!(PinnedId::of(&a).is(&a));
+        // Same address, different size.
+        assert!(!PinnedId::of(&a).is(&slice));
+    }
+}
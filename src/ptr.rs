@@ -0,0 +1,307 @@
+//! Implementations for raw pointers and function pointers.
+//!
+//! Raw pointers are compared, hashed and ordered by their address, so
+//! `*const T` pointing to an object behaves exactly like `&T` pointing to
+//! the same object. Pointers to slices and `str` are compared by address and
+//! size, which is computed from the length stored in the pointer, also like
+//! references. Pointers to other unsized types, such as trait objects, are
+//! not supported, since their size can't be determined without dereferencing
+//! them, which could be unsound for dangling pointers.
+//!
+//! Function pointers are compared by address as well. Note that the compiler
+//! may merge identical functions or emit several copies of the same one, so
+//! the result of comparing pointers to different functions (or to the same
+//! generic function instantiated in different crates) is not guaranteed.
+//! Higher-ranked function pointers such as `fn(&T)` are not supported.
+
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::mem;
+use core::ptr::{self, NonNull};
+
+use {fmt_addr, FmtAddress, RefHash, RefOrd, Same};
+
+impl<T> Same for *const T {
+    fn same(&self, other: &Self) -> bool {
+        ptr::eq(*self, *other)
+    }
+}
+
+impl<T> Same for *mut T {
+    fn same(&self, other: &Self) -> bool {
+        ptr::eq(*self, *other)
+    }
+}
+
+impl<T> Same for NonNull<T> {
+    fn same(&self, other: &Self) -> bool {
+        self.as_ptr().same(&other.as_ptr())
+    }
+}
+
+impl<T> RefHash for *const T {
+    fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+        (*self as usize).hash(hasher);
+    }
+}
+
+impl<T> RefHash for *mut T {
+    fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+        (*self as usize).hash(hasher);
+    }
+}
+
+impl<T> RefHash for NonNull<T> {
+    fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+        self.as_ptr().ref_hash(hasher);
+    }
+}
+
+impl<T> RefOrd for *const T {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        (*self as usize).cmp(&(*other as usize))
+    }
+}
+
+impl<T> RefOrd for *mut T {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        (*self as usize).cmp(&(*other as usize))
+    }
+}
+
+impl<T> RefOrd for NonNull<T> {
+    fn ref_cmp(&self, other: &Self) -> Ordering {
+        self.as_ptr().ref_cmp(&other.as_ptr())
+    }
+}
+
+impl<T> FmtAddress for *const T {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_addr(*self as usize, f)
+    }
+}
+
+impl<T> FmtAddress for *mut T {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_addr(*self as usize, f)
+    }
+}
+
+impl<T> FmtAddress for NonNull<T> {
+    fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_ptr().fmt_address(f)
+    }
+}
+
+/// Raw pointers to slices, whose identity can be determined without
+/// dereferencing them.
+trait RawSlice {
+    /// Returns the address and the size of the slice in bytes.
+    fn parts(&self) -> (usize, usize);
+}
+
+impl<T> RawSlice for *const [T] {
+    fn parts(&self) -> (usize, usize) {
+        (*self as *const T as usize, mem::size_of::<T>().saturating_mul(self.len()))
+    }
+}
+
+impl<T> RawSlice for *mut [T] {
+    fn parts(&self) -> (usize, usize) {
+        (*self as *const [T]).parts()
+    }
+}
+
+impl<T> RawSlice for NonNull<[T]> {
+    fn parts(&self) -> (usize, usize) {
+        (self.as_ptr() as *const [T]).parts()
+    }
+}
+
+impl RawSlice for *const str {
+    fn parts(&self) -> (usize, usize) {
+        (*self as *const [u8]).parts()
+    }
+}
+
+impl RawSlice for *mut str {
+    fn parts(&self) -> (usize, usize) {
+        (*self as *const [u8]).parts()
+    }
+}
+
+impl RawSlice for NonNull<str> {
+    fn parts(&self) -> (usize, usize) {
+        (self.as_ptr() as *const [u8]).parts()
+    }
+}
+
+/// Implements the traits for raw pointers to slices consistently with `&T`:
+/// the address and the size are compared, but only the address is hashed.
+macro_rules! raw_slice_impls {
+    ($([$($param:ident),*] $ty:ty),*) => {
+        $(
+            impl<$($param),*> Same for $ty {
+                fn same(&self, other: &Self) -> bool {
+                    self.parts() == other.parts()
+                }
+            }
+
+            impl<$($param),*> RefHash for $ty {
+                fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+                    self.parts().0.hash(hasher);
+                }
+            }
+
+            impl<$($param),*> RefOrd for $ty {
+                fn ref_cmp(&self, other: &Self) -> Ordering {
+                    self.parts().cmp(&other.parts())
+                }
+            }
+
+            impl<$($param),*> FmtAddress for $ty {
+                fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    fmt_addr(self.parts().0, f)
+                }
+            }
+        )*
+    };
+}
+
+raw_slice_impls!([T] *const [T], [T] *mut [T], [T] NonNull<[T]>, [] *const str, [] *mut str, [] NonNull<str>);
+
+/// Implements the traits for function pointers taking the given arguments,
+/// with all combinations of `unsafe` and `extern "C"`.
+macro_rules! fn_impls {
+    ($($arg:ident),*) => {
+        fn_impls!(@impl [$($arg),*] fn($($arg),*) -> Ret);
+        fn_impls!(@impl [$($arg),*] unsafe fn($($arg),*) -> Ret);
+        fn_impls!(@impl [$($arg),*] extern "C" fn($($arg),*) -> Ret);
+        fn_impls!(@impl [$($arg),*] unsafe extern "C" fn($($arg),*) -> Ret);
+    };
+    (@impl [$($arg:ident),*] $ty:ty) => {
+        impl<Ret, $($arg),*> Same for $ty {
+            fn same(&self, other: &Self) -> bool {
+                *self as usize == *other as usize
+            }
+        }
+
+        impl<Ret, $($arg),*> RefHash for $ty {
+            fn ref_hash<H: Hasher>(&self, hasher: &mut H) {
+                (*self as usize).hash(hasher);
+            }
+        }
+
+        impl<Ret, $($arg),*> RefOrd for $ty {
+            fn ref_cmp(&self, other: &Self) -> Ordering {
+                (*self as usize).cmp(&(*other as usize))
+            }
+        }
+
+        impl<Ret, $($arg),*> FmtAddress for $ty {
+            fn fmt_address(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt_addr(*self as usize, f)
+            }
+        }
+    };
+}
+
+fn_impls!();
+fn_impls!(A);
+fn_impls!(A, B);
+fn_impls!(A, B, C);
+fn_impls!(A, B, C, D);
+fn_impls!(A, B, C, D, E);
+fn_impls!(A, B, C, D, E, F);
+fn_impls!(A, B, C, D, E, F, G);
+fn_impls!(A, B, C, D, E, F, G, I);
+fn_impls!(A, B, C, D, E, F, G, I, J);
+fn_impls!(A, B, C, D, E, F, G, I, J, K);
+fn_impls!(A, B, C, D, E, F, G, I, J, K, L);
+fn_impls!(A, B, C, D, E, F, G, I, J, K, L, M);
+
+#[cfg(test)]
+mod tests {
+    use core::ptr::NonNull;
+    use std::collections::HashSet;
+    use {RefCmp, RefHash, Same};
+
+    fn hash<T: RefHash>(value: &T) -> u64 {
+        use std::hash::Hasher;
+
+        let mut hasher = ::std::collections::hash_map::DefaultHasher::new();
+        value.ref_hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn raw_pointers() {
+        let mut a = 42;
+        let b = 42;
+        let a_ref = &a;
+        let a_ptr: *const i32 = a_ref;
+
+        assert!(a_ptr.same(&(&a as *const i32)));
+        assert!(!a_ptr.same(&(&b as *const i32)));
+        // Consistent with references.
+        assert_eq!(hash(&a_ptr), hash(&a_ref));
+
+        let a_mut: *mut i32 = &mut a;
+        let a_non_null = NonNull::new(a_mut).unwrap();
+        assert!(a_mut.same(&a_non_null.as_ptr()));
+        assert!(a_non_null.same(&NonNull::from(&a)));
+        assert!(!a_non_null.same(&NonNull::dangling()));
+        assert_eq!(hash(&a_mut), hash(&a_non_null));
+    }
+
+    #[test]
+    fn slice_pointers() {
+        use RefOrd;
+        use std::cmp::Ordering;
+
+        let mut array = [1, 2, 3];
+        let whole: *const [i32] = &array;
+        let prefix: *const [i32] = &array[..2];
+
+        assert!(whole.same(&(&array[..] as *const [i32])));
+        // Same address, different length.
+        assert!(!whole.same(&prefix));
+        assert_eq!(prefix.ref_cmp(&whole), Ordering::Less);
+        // Consistent with references.
+        assert_eq!(hash(&whole), hash(&&array[..]));
+        assert_eq!(hash(&whole), hash(&prefix));
+
+        let whole_mut: *mut [i32] = &mut array;
+        assert!(NonNull::new(whole_mut).unwrap().same(&NonNull::from(&array[..])));
+
+        let text = "héllo";
+        let text_ptr: *const str = text;
+        assert!(text_ptr.same(&(text as *const str)));
+        assert!(!text_ptr.same(&(&text[..3] as *const str)));
+        assert_eq!(hash(&text_ptr), hash(&NonNull::from(text)));
+    }
+
+    #[test]
+    fn fn_pointers() {
+        fn answer() -> u32 {
+            42
+        }
+
+        extern "C" fn add(a: u32, b: u32) -> u32 {
+            a.wrapping_add(b)
+        }
+
+        extern "C" fn sub(a: u32, b: u32) -> u32 {
+            a.wrapping_sub(b)
+        }
+
+        let answer: fn() -> u32 = answer;
+        assert!(answer.same(&answer));
+
+        let mut callbacks = HashSet::new();
+        assert!(callbacks.insert(RefCmp(add as extern "C" fn(u32, u32) -> u32)));
+        assert!(!callbacks.insert(RefCmp(add as extern "C" fn(u32, u32) -> u32)));
+        assert!(callbacks.insert(RefCmp(sub as extern "C" fn(u32, u32) -> u32)));
+    }
+}
@@ -0,0 +1,201 @@
+//! Queries about memory regions occupied by objects.
+//!
+//! While `Same` tests whether two references point to the same object, the
+//! functions in this module test how the memory of objects relates: whether
+//! a reference points into a slice (and at which index), whether a slice is
+//! a part of another one, or whether two objects overlap. All of them work
+//! only with addresses and sizes, so they run in O(1) and never read the
+//! objects.
+//!
+//! # Zero-sized types
+//!
+//! Objects of zero-sized types don't occupy any memory, so they never
+//! overlap anything and their position in a slice can't be determined: all
+//! elements of a slice of zero-sized types have the same address. For such
+//! slices, `index_of` and `range_of` consider only the beginning of the
+//! slice, see their documentation.
+//!
+//! # Example
+//!
+//! ```
+//! use same::region;
+//!
+//! let text = String::from("let x = 42;");
+//! let token = text.split(' ').nth(1).unwrap();
+//!
+//! assert_eq!(region::str_range_of(&text, token), Some(4..5));
+//! assert!(region::contains(text.as_str(), token));
+//! assert!(!region::contains(text.as_str(), "x"));
+//! ```
+
+use core::mem;
+use core::ops::Range;
+
+use addr;
+
+/// Returns the range of addresses occupied by the object.
+fn bytes<T: ?Sized>(object: &T) -> Range<usize> {
+    let start = addr(object);
+    start..start + mem::size_of_val(object)
+}
+
+/// Returns the index of the element `item` points to, or `None` if it
+/// doesn't point into `slice`.
+///
+/// Only the address is compared, the values are not, so this finds the
+/// element itself, not an equal one.
+///
+/// If `T` is zero-sized, `Some(0)` is returned if `item` has the same
+/// address as a non-empty slice, since the actual index can't be recovered.
+///
+/// # Example
+///
+/// ```
+/// use same::region;
+///
+/// let values = [1, 2, 1];
+/// let last = values.iter().rev().next().unwrap();
+///
+/// assert_eq!(region::index_of(&values, last), Some(2));
+/// assert_eq!(region::index_of(&values, &1), None);
+/// ```
+pub fn index_of<T>(slice: &[T], item: &T) -> Option<usize> {
+    let start = addr(slice.as_ptr());
+    let item = addr(item);
+    let size = mem::size_of::<T>();
+
+    if size == 0 {
+        return if item == start && !slice.is_empty() { Some(0) } else { None };
+    }
+
+    let offset = item.checked_sub(start)?;
+    let index = offset / size;
+    if offset % size == 0 && index < slice.len() {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+/// Returns the range of indices of `outer` which `inner` refers to, or
+/// `None` if `inner` isn't a part of `outer`.
+///
+/// An empty `inner` slice is a part of `outer` if it points to any element
+/// of `outer` or right past its end.
+///
+/// If `T` is zero-sized, the range starting at 0 is returned if both slices
+/// start at the same address and `inner` isn't longer than `outer`.
+pub fn range_of<T>(outer: &[T], inner: &[T]) -> Option<Range<usize>> {
+    let size = mem::size_of::<T>();
+    let start = if size == 0 {
+        if addr(outer.as_ptr()) != addr(inner.as_ptr()) {
+            return None;
+        }
+        0
+    } else {
+        let offset = addr(inner.as_ptr()).checked_sub(addr(outer.as_ptr()))?;
+        if offset % size != 0 {
+            return None;
+        }
+        offset / size
+    };
+
+    let end = start.checked_add(inner.len())?;
+    if end <= outer.len() {
+        Some(start..end)
+    } else {
+        None
+    }
+}
+
+/// Returns the byte range of `outer` which `inner` refers to, or `None` if
+/// `inner` isn't a part of `outer`.
+///
+/// This is useful for recovering positions of tokens produced by a
+/// zero-copy parser. The returned range can be used to index `outer`.
+pub fn str_range_of(outer: &str, inner: &str) -> Option<Range<usize>> {
+    range_of(outer.as_bytes(), inner.as_bytes())
+}
+
+/// Returns `true` if the memory of `inner` lies entirely within the memory
+/// of `outer`.
+///
+/// Zero-sized objects are contained if their address is within `outer` or
+/// right past its end.
+pub fn contains<T: ?Sized, U: ?Sized>(outer: &T, inner: &U) -> bool {
+    let outer = bytes(outer);
+    let inner = bytes(inner);
+    outer.start <= inner.start && inner.end <= outer.end
+}
+
+/// Returns `true` if `a` and `b` share at least one byte of memory.
+///
+/// Zero-sized objects don't overlap anything, including themselves.
+///
+/// # Example
+///
+/// ```
+/// use same::region;
+///
+/// let buffer = [0u8; 16];
+///
+/// assert!(region::overlaps(&buffer[..8], &buffer[4..]));
+/// assert!(!region::overlaps(&buffer[..8], &buffer[8..]));
+/// ```
+pub fn overlaps<T: ?Sized, U: ?Sized>(a: &T, b: &U) -> bool {
+    let a = bytes(a);
+    let b = bytes(b);
+    a.start < b.end && b.start < a.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{contains, index_of, overlaps, range_of, str_range_of};
+
+    #[test]
+    fn indices() {
+        let values = [1u32, 2, 3, 4];
+        let other = 2u32;
+
+        assert_eq!(index_of(&values, &values[1]), Some(1));
+        assert_eq!(index_of(&values[1..], &values[3]), Some(2));
+        assert_eq!(index_of(&values[1..], &values[0]), None);
+        assert_eq!(index_of(&values[..2], &values[2]), None);
+        assert_eq!(index_of(&values, &other), None);
+
+        assert_eq!(range_of(&values, &values[1..3]), Some(1..3));
+        assert_eq!(range_of(&values, &values[4..]), Some(4..4));
+        assert_eq!(range_of(&values[..2], &values[1..3]), None);
+        assert_eq!(range_of(&values[1..], &values[..1]), None);
+
+        let s = "héllo";
+        assert_eq!(str_range_of(s, &s[1..3]), Some(1..3));
+    }
+
+    #[test]
+    fn containment() {
+        let values = [1u16, 2, 3, 4];
+
+        assert!(contains(&values, &values[2]));
+        assert!(contains(&values[..], &values[1..3]));
+        assert!(!contains(&values[1..], &values[..2]));
+        assert!(!contains(&values[0], &values));
+
+        assert!(overlaps(&values[..2], &values[1..]));
+        assert!(overlaps(&values, &values[3]));
+        assert!(!overlaps(&values[..2], &values[2..]));
+        assert!(!overlaps(&values[..0], &values[..0]));
+    }
+
+    #[test]
+    fn zero_sized() {
+        let values = [(), (), ()];
+
+        assert_eq!(index_of(&values, &values[2]), Some(0));
+        assert_eq!(index_of(&values[..0], &values[0]), None);
+        assert_eq!(range_of(&values, &values[1..]), Some(0..2));
+        assert_eq!(range_of(&values[..1], &values[..]), None);
+        assert!(contains(&values, &values[1]));
+        assert!(!overlaps(&values, &values));
+    }
+}
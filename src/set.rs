@@ -0,0 +1,427 @@
+//! Hash set of objects compared by identity.
+//!
+//! See `IdentitySet` for more information.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::BuildHasher;
+use core::iter::{Chain, FromIterator};
+use std::collections::hash_map::RandomState;
+
+use map::{self, IdentityMap};
+use {Lookup, RefCmp, RefHash, Same};
+
+/// Hash set comparing elements by identity of the objects they point to.
+///
+/// This is a more convenient equivalent of `HashSet<RefCmp<T>>`. Elements can
+/// be any type implementing `Same` and `RefHash` and two elements are
+/// considered equal if they are the same. Just like `IdentityMap`, the set can
+/// be looked up by a reference to an element or, for handles, by a plain
+/// reference to the object.
+///
+/// # Example
+///
+/// ```
+/// use same::IdentitySet;
+/// use std::rc::Rc;
+///
+/// let a = Rc::new(42);
+/// let b = Rc::new(42);
+///
+/// let mut set = IdentitySet::new();
+/// assert!(set.insert(a.clone()));
+/// assert!(!set.insert(a.clone()));
+/// assert!(set.insert(b.clone()));
+///
+/// assert!(set.contains(&a));
+/// assert!(!set.contains(&42));
+/// ```
+pub struct IdentitySet<T, S = RandomState> {
+    map: IdentityMap<T, (), S>,
+}
+
+impl<T: Same + RefHash> IdentitySet<T, RandomState> {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        IdentitySet { map: IdentityMap::new() }
+    }
+
+    /// Creates an empty set with at least the specified capacity.
+    pub fn with_capacity(capacity: usize) -> Self {
+        IdentitySet { map: IdentityMap::with_capacity(capacity) }
+    }
+}
+
+impl<T, S> IdentitySet<T, S> {
+    /// Creates an empty set which will use the given hash builder.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        IdentitySet { map: IdentityMap::with_hasher(hash_builder) }
+    }
+
+    /// Creates an empty set with at least the specified capacity, using
+    /// `hash_builder` to hash the elements.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        IdentitySet { map: IdentityMap::with_capacity_and_hasher(capacity, hash_builder) }
+    }
+
+    /// Returns the number of elements the set can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    /// Returns a reference to the set's `BuildHasher`.
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the set contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all elements from the set.
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    /// Iterates over the elements in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { iter: self.map.keys() }
+    }
+
+    /// Removes all elements from the set and returns them as an iterator,
+    /// keeping the allocated memory for reuse.
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain { iter: self.map.drain() }
+    }
+
+    /// Retains only the elements for which the predicate returns `true`.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        self.map.retain(|key, _| f(key))
+    }
+}
+
+impl<T: Same + RefHash, S: BuildHasher> IdentitySet<T, S> {
+    /// Reserves capacity for at least `additional` more elements.
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional)
+    }
+
+    /// Shrinks the capacity of the set as much as possible.
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit()
+    }
+
+    /// Returns `true` if the set contains an element which is the same as
+    /// `value`.
+    pub fn contains<Q: ?Sized + Lookup<T>>(&self, value: &Q) -> bool where RefCmp<T>: Borrow<Q::Key> {
+        self.map.contains_key(value)
+    }
+
+    /// Returns the stored element which is the same as `value`.
+    pub fn get<Q: ?Sized + Lookup<T>>(&self, value: &Q) -> Option<&T> where RefCmp<T>: Borrow<Q::Key> {
+        self.map.get_key_value(value).map(|(key, _)| key)
+    }
+
+    /// Adds the element to the set.
+    ///
+    /// Returns `true` if the set didn't contain an element which is the same.
+    /// The stored element is not updated.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.map.insert(value, ()).is_none()
+    }
+
+    /// Removes the element from the set, returning `true` if it was present.
+    pub fn remove<Q: ?Sized + Lookup<T>>(&mut self, value: &Q) -> bool where RefCmp<T>: Borrow<Q::Key> {
+        self.map.remove(value).is_some()
+    }
+
+    /// Removes the element from the set, returning the stored element.
+    pub fn take<Q: ?Sized + Lookup<T>>(&mut self, value: &Q) -> Option<T> where RefCmp<T>: Borrow<Q::Key> {
+        self.map.remove_entry(value).map(|(key, _)| key)
+    }
+
+    /// Iterates over elements that are in `self` or in `other`, without
+    /// duplicates.
+    pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, T, S> {
+        Union { iter: self.iter().chain(other.difference(self)) }
+    }
+
+    /// Iterates over elements that are both in `self` and in `other`.
+    ///
+    /// The elements are yielded from the smaller set.
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> Intersection<'a, T, S> {
+        if self.len() <= other.len() {
+            Intersection { iter: self.iter(), other }
+        } else {
+            Intersection { iter: other.iter(), other: self }
+        }
+    }
+
+    /// Iterates over elements that are in `self` but not in `other`.
+    pub fn difference<'a>(&'a self, other: &'a Self) -> Difference<'a, T, S> {
+        Difference { iter: self.iter(), other }
+    }
+
+    /// Iterates over elements that are in `self` or in `other`, but not in both.
+    pub fn symmetric_difference<'a>(&'a self, other: &'a Self) -> SymmetricDifference<'a, T, S> {
+        SymmetricDifference { iter: self.difference(other).chain(other.difference(self)) }
+    }
+
+    /// Returns `true` if `self` has no elements in common with `other`.
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        self.intersection(other).next().is_none()
+    }
+
+    /// Returns `true` if all elements of `self` are in `other`.
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.len() <= other.len() && self.difference(other).next().is_none()
+    }
+
+    /// Returns `true` if all elements of `other` are in `self`.
+    pub fn is_superset(&self, other: &Self) -> bool {
+        other.is_subset(self)
+    }
+}
+
+impl<T: Clone, S: Clone> Clone for IdentitySet<T, S> {
+    fn clone(&self) -> Self {
+        IdentitySet { map: self.map.clone() }
+    }
+}
+
+impl<T: Same + RefHash, S: Default> Default for IdentitySet<T, S> {
+    fn default() -> Self {
+        IdentitySet { map: IdentityMap::default() }
+    }
+}
+
+impl<T: fmt::Debug, S> fmt::Debug for IdentitySet<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Same + RefHash, S: BuildHasher> Extend<T> for IdentitySet<T, S> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.map.extend(iter.into_iter().map(|value| (value, ())))
+    }
+}
+
+impl<T: Same + RefHash, S: BuildHasher + Default> FromIterator<T> for IdentitySet<T, S> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut set = IdentitySet::with_hasher(S::default());
+        set.extend(iter);
+        set
+    }
+}
+
+impl<'a, T, S> IntoIterator for &'a IdentitySet<T, S> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, S> IntoIterator for IdentitySet<T, S> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { iter: self.map.into_iter() }
+    }
+}
+
+/// Iterator over elements of `IdentitySet`.
+pub struct Iter<'a, T: 'a> {
+    iter: map::Keys<'a, T, ()>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> Clone for Iter<'a, T> {
+    fn clone(&self) -> Self {
+        Iter { iter: self.iter.clone() }
+    }
+}
+
+/// Draining iterator of `IdentitySet`.
+pub struct Drain<'a, T: 'a> {
+    iter: map::Drain<'a, T, ()>,
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Drain<'a, T> {}
+
+/// Owning iterator of `IdentitySet`.
+pub struct IntoIter<T> {
+    iter: map::IntoIter<T, ()>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+/// Iterator over the union of two `IdentitySet`s.
+///
+/// Returned by `IdentitySet::union`.
+pub struct Union<'a, T: 'a, S: 'a> {
+    iter: Chain<Iter<'a, T>, Difference<'a, T, S>>,
+}
+
+impl<'a, T: Same + RefHash, S: BuildHasher> Iterator for Union<'a, T, S> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+/// Iterator over the intersection of two `IdentitySet`s.
+///
+/// Returned by `IdentitySet::intersection`.
+pub struct Intersection<'a, T: 'a, S: 'a> {
+    iter: Iter<'a, T>,
+    other: &'a IdentitySet<T, S>,
+}
+
+impl<'a, T: Same + RefHash, S: BuildHasher> Iterator for Intersection<'a, T, S> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let other = self.other;
+        self.iter.by_ref().find(|value| other.contains(*value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+/// Iterator over the difference of two `IdentitySet`s.
+///
+/// Returned by `IdentitySet::difference`.
+pub struct Difference<'a, T: 'a, S: 'a> {
+    iter: Iter<'a, T>,
+    other: &'a IdentitySet<T, S>,
+}
+
+impl<'a, T: Same + RefHash, S: BuildHasher> Iterator for Difference<'a, T, S> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let other = self.other;
+        self.iter.by_ref().find(|value| !other.contains(*value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+/// Iterator over the symmetric difference of two `IdentitySet`s.
+///
+/// Returned by `IdentitySet::symmetric_difference`.
+pub struct SymmetricDifference<'a, T: 'a, S: 'a> {
+    iter: Chain<Difference<'a, T, S>, Difference<'a, T, S>>,
+}
+
+impl<'a, T: Same + RefHash, S: BuildHasher> Iterator for SymmetricDifference<'a, T, S> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdentitySet;
+    use std::rc::Rc;
+    use std::string::String;
+    use std::vec::Vec;
+
+    #[test]
+    fn set_algebra() {
+        let a = Rc::new(1);
+        let b = Rc::new(1);
+        let c = Rc::new(1);
+
+        let ab = [a.clone(), b.clone()].iter().cloned().collect::<IdentitySet<_>>();
+        let bc = [b.clone(), c.clone()].iter().cloned().collect::<IdentitySet<_>>();
+
+        let union = ab.union(&bc).collect::<Vec<_>>();
+        assert_eq!(union.len(), 3);
+
+        let intersection = ab.intersection(&bc).collect::<Vec<_>>();
+        assert_eq!(intersection.len(), 1);
+        assert!(Rc::ptr_eq(intersection[0], &b));
+
+        let difference = ab.difference(&bc).collect::<Vec<_>>();
+        assert_eq!(difference.len(), 1);
+        assert!(Rc::ptr_eq(difference[0], &a));
+
+        let symmetric = ab.symmetric_difference(&bc).cloned().collect::<IdentitySet<_>>();
+        assert_eq!(symmetric.len(), 2);
+        assert!(symmetric.contains(&a));
+        assert!(symmetric.contains(&c));
+
+        assert!(!ab.is_disjoint(&bc));
+        assert!(ab.is_superset(&[a.clone()].iter().cloned().collect()));
+        assert!(!ab.is_subset(&bc));
+    }
+
+    #[test]
+    fn lookup_by_reference() {
+        let a = Rc::new(String::from("a"));
+        let b = Rc::new(String::from("a"));
+
+        let mut set = IdentitySet::new();
+        set.insert(a.clone());
+        assert!(set.contains(&a));
+        assert!(!set.contains(&b));
+        assert!(Rc::ptr_eq(set.get(&*a).unwrap(), &a));
+        assert!(set.take(&a).is_some());
+        assert!(set.is_empty());
+    }
+}
@@ -0,0 +1,59 @@
+//! Abstraction over `Rc` and `Arc` used by modules rebuilding object
+//! graphs.
+
+use std::rc::{self, Rc};
+use std::sync::{self, Arc};
+
+/// `Rc` or `Arc` pointing to a sized value.
+pub(crate) trait Shared: Clone + 'static {
+    type Target: 'static;
+    type Weak: Clone + 'static;
+
+    fn new(value: Self::Target) -> Self;
+    fn new_cyclic<F: FnOnce(&Self::Weak) -> Self::Target>(f: F) -> Self;
+    fn target(&self) -> &Self::Target;
+    fn downgrade(&self) -> Self::Weak;
+    fn upgrade(weak: &Self::Weak) -> Option<Self>;
+    fn dangling() -> Self::Weak;
+    fn weak_ptr(weak: &Self::Weak) -> *const Self::Target;
+}
+
+macro_rules! shared_impl {
+    ($ptr:ident, $weak:path) => {
+        impl<T: 'static> Shared for $ptr<T> {
+            type Target = T;
+            type Weak = $weak;
+
+            fn new(value: T) -> Self {
+                $ptr::new(value)
+            }
+
+            fn new_cyclic<F: FnOnce(&Self::Weak) -> T>(f: F) -> Self {
+                $ptr::new_cyclic(f)
+            }
+
+            fn target(&self) -> &T {
+                self
+            }
+
+            fn downgrade(&self) -> Self::Weak {
+                $ptr::downgrade(self)
+            }
+
+            fn upgrade(weak: &Self::Weak) -> Option<Self> {
+                weak.upgrade()
+            }
+
+            fn dangling() -> Self::Weak {
+                <$weak>::new()
+            }
+
+            fn weak_ptr(weak: &Self::Weak) -> *const T {
+                weak.as_ptr()
+            }
+        }
+    };
+}
+
+shared_impl!(Rc, rc::Weak<T>);
+shared_impl!(Arc, sync::Weak<T>);
@@ -0,0 +1,349 @@
+//! Traversal of graphs made of shared pointers.
+//!
+//! Graphs built from `Rc`, `Arc` or plain references may share nodes and may
+//! even contain cycles, so walking them requires remembering which nodes
+//! were already visited. The iterators in this module keep an `IdentitySet`
+//! of visited handles, so every allocation is visited exactly once and
+//! cycles terminate.
+//!
+//! To traverse a graph, implement `Visit` for the node type.
+//!
+//! # Example
+//!
+//! ```
+//! use same::visit::{self, Visit};
+//! use std::cell::RefCell;
+//! use std::rc::Rc;
+//!
+//! struct Node {
+//!     name: &'static str,
+//!     edges: RefCell<Vec<Rc<Node>>>,
+//! }
+//!
+//! impl Visit for Node {
+//!     type Handle = Rc<Node>;
+//!
+//!     fn for_each_child<F: FnMut(&Rc<Node>)>(&self, f: F) {
+//!         self.edges.borrow().iter().for_each(f)
+//!     }
+//! }
+//!
+//! let node = |name| Rc::new(Node { name, edges: RefCell::new(Vec::new()) });
+//! let a = node("a");
+//! let b = node("b");
+//! let c = node("c");
+//! a.edges.borrow_mut().extend(vec![b.clone(), c.clone()]);
+//! b.edges.borrow_mut().push(c.clone());
+//! // Cycle
+//! c.edges.borrow_mut().push(a.clone());
+//!
+//! let names = visit::Dfs::new(a.clone()).map(|node| node.name).collect::<Vec<_>>();
+//! assert_eq!(names, ["a", "b", "c"]);
+//!
+//! let names = visit::Bfs::new(a.clone()).map(|node| node.name).collect::<Vec<_>>();
+//! assert_eq!(names, ["a", "b", "c"]);
+//!
+//! let names = visit::DfsPostOrder::new(a.clone()).map(|node| node.name).collect::<Vec<_>>();
+//! assert_eq!(names, ["c", "b", "a"]);
+//!
+//! assert_eq!(visit::reachable(b.clone()).len(), 3);
+//! # // Break the cycle to avoid leaking memory.
+//! # c.edges.borrow_mut().clear();
+//! ```
+
+use core::ops::Deref;
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+use {IdentitySet, RefHash, Same};
+
+/// Node of a graph that can be traversed.
+pub trait Visit {
+    /// Shared pointer to a node, such as `Rc<Self>`.
+    ///
+    /// Handles are compared using `Same` and `RefHash` to track visited nodes.
+    type Handle: Deref<Target = Self> + Clone + Same + RefHash;
+
+    /// Calls `f` with each outgoing edge of the node.
+    ///
+    /// The order of the calls determines the order of traversal.
+    fn for_each_child<F: FnMut(&Self::Handle)>(&self, f: F);
+}
+
+/// Depth-first pre-order iterator.
+///
+/// Each node is yielded before its children.
+pub struct Dfs<H> {
+    stack: Vec<H>,
+    visited: IdentitySet<H>,
+}
+
+impl<H> Dfs<H>
+where
+    H: Deref + Clone + Same + RefHash,
+    H::Target: Visit<Handle = H>,
+{
+    /// Starts the traversal at `root`.
+    pub fn new(root: H) -> Self {
+        Self::from_roots(Some(root))
+    }
+
+    /// Starts the traversal at multiple roots, which are visited in order.
+    pub fn from_roots<I: IntoIterator<Item = H>>(roots: I) -> Self {
+        let mut stack = roots.into_iter().collect::<Vec<_>>();
+        stack.reverse();
+
+        Dfs { stack, visited: IdentitySet::new() }
+    }
+
+    /// Returns the set of nodes visited so far.
+    pub fn visited(&self) -> &IdentitySet<H> {
+        &self.visited
+    }
+
+    /// Stops the traversal and returns the set of visited nodes.
+    pub fn into_visited(self) -> IdentitySet<H> {
+        self.visited
+    }
+}
+
+impl<H> Iterator for Dfs<H>
+where
+    H: Deref + Clone + Same + RefHash,
+    H::Target: Visit<Handle = H>,
+{
+    type Item = H;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            if !self.visited.insert(node.clone()) {
+                continue;
+            }
+
+            let start = self.stack.len();
+            let visited = &self.visited;
+            let stack = &mut self.stack;
+            node.for_each_child(|child| if !visited.contains(child) {
+                stack.push(child.clone());
+            });
+            // The first child must be on top of the stack.
+            self.stack[start..].reverse();
+
+            return Some(node);
+        }
+        None
+    }
+}
+
+/// Depth-first post-order iterator.
+///
+/// Each node is yielded after all its children. If the graph contains a
+/// cycle, the node that closes it is yielded before the node it points to.
+pub struct DfsPostOrder<H> {
+    /// Nodes with their children that weren't processed yet, in reverse order.
+    stack: Vec<(H, Vec<H>)>,
+    roots: Vec<H>,
+    visited: IdentitySet<H>,
+}
+
+impl<H> DfsPostOrder<H>
+where
+    H: Deref + Clone + Same + RefHash,
+    H::Target: Visit<Handle = H>,
+{
+    /// Starts the traversal at `root`.
+    pub fn new(root: H) -> Self {
+        Self::from_roots(Some(root))
+    }
+
+    /// Starts the traversal at multiple roots, which are visited in order.
+    pub fn from_roots<I: IntoIterator<Item = H>>(roots: I) -> Self {
+        let mut roots = roots.into_iter().collect::<Vec<_>>();
+        roots.reverse();
+
+        DfsPostOrder { stack: Vec::new(), roots, visited: IdentitySet::new() }
+    }
+
+    /// Returns the set of nodes discovered so far.
+    ///
+    /// This includes nodes that weren't yielded yet because their children
+    /// are still being processed.
+    pub fn visited(&self) -> &IdentitySet<H> {
+        &self.visited
+    }
+
+    /// Stops the traversal and returns the set of discovered nodes.
+    pub fn into_visited(self) -> IdentitySet<H> {
+        self.visited
+    }
+
+    fn discover(&mut self, node: H) {
+        if self.visited.insert(node.clone()) {
+            let mut children = Vec::new();
+            node.for_each_child(|child| children.push(child.clone()));
+            children.reverse();
+            self.stack.push((node, children));
+        }
+    }
+}
+
+impl<H> Iterator for DfsPostOrder<H>
+where
+    H: Deref + Clone + Same + RefHash,
+    H::Target: Visit<Handle = H>,
+{
+    type Item = H;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let child = match self.stack.last_mut() {
+                Some(&mut (_, ref mut children)) => children.pop(),
+                None => match self.roots.pop() {
+                    Some(root) => Some(root),
+                    None => return None,
+                },
+            };
+
+            match child {
+                Some(child) => self.discover(child),
+                None => return self.stack.pop().map(|(node, _)| node),
+            }
+        }
+    }
+}
+
+/// Breadth-first iterator.
+///
+/// Nodes are yielded in the order of their distance from the root.
+pub struct Bfs<H> {
+    queue: VecDeque<H>,
+    visited: IdentitySet<H>,
+}
+
+impl<H> Bfs<H>
+where
+    H: Deref + Clone + Same + RefHash,
+    H::Target: Visit<Handle = H>,
+{
+    /// Starts the traversal at `root`.
+    pub fn new(root: H) -> Self {
+        Self::from_roots(Some(root))
+    }
+
+    /// Starts the traversal at multiple roots.
+    pub fn from_roots<I: IntoIterator<Item = H>>(roots: I) -> Self {
+        let mut queue = VecDeque::new();
+        let mut visited = IdentitySet::new();
+        for root in roots {
+            if visited.insert(root.clone()) {
+                queue.push_back(root);
+            }
+        }
+
+        Bfs { queue, visited }
+    }
+
+    /// Returns the set of nodes discovered so far.
+    ///
+    /// This includes nodes that are queued but weren't yielded yet.
+    pub fn visited(&self) -> &IdentitySet<H> {
+        &self.visited
+    }
+
+    /// Stops the traversal and returns the set of discovered nodes.
+    pub fn into_visited(self) -> IdentitySet<H> {
+        self.visited
+    }
+}
+
+impl<H> Iterator for Bfs<H>
+where
+    H: Deref + Clone + Same + RefHash,
+    H::Target: Visit<Handle = H>,
+{
+    type Item = H;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.queue.pop_front()?;
+
+        let visited = &mut self.visited;
+        let queue = &mut self.queue;
+        node.for_each_child(|child| if visited.insert(child.clone()) {
+            queue.push_back(child.clone());
+        });
+
+        Some(node)
+    }
+}
+
+/// Returns the set of all nodes reachable from `root`, including `root`.
+pub fn reachable<H>(root: H) -> IdentitySet<H>
+where
+    H: Deref + Clone + Same + RefHash,
+    H::Target: Visit<Handle = H>,
+{
+    let mut dfs = Dfs::new(root);
+    dfs.by_ref().for_each(drop);
+    dfs.into_visited()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bfs, Dfs, DfsPostOrder, Visit};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    struct Node {
+        id: u32,
+        edges: RefCell<Vec<Rc<Node>>>,
+    }
+
+    impl Visit for Node {
+        type Handle = Rc<Node>;
+
+        fn for_each_child<F: FnMut(&Rc<Node>)>(&self, f: F) {
+            self.edges.borrow().iter().for_each(f)
+        }
+    }
+
+    /// Creates a diamond `0 -> {1, 2} -> 3` with a back edge `3 -> 0`.
+    fn graph() -> Vec<Rc<Node>> {
+        let nodes = (0..4).map(|id| Rc::new(Node { id, edges: RefCell::new(Vec::new()) })).collect::<Vec<_>>();
+        nodes[0].edges.borrow_mut().extend(nodes[1..3].iter().cloned());
+        nodes[1].edges.borrow_mut().push(nodes[3].clone());
+        nodes[2].edges.borrow_mut().push(nodes[3].clone());
+        nodes[3].edges.borrow_mut().push(nodes[0].clone());
+        nodes
+    }
+
+    fn ids<I: Iterator<Item = Rc<Node>>>(iter: I) -> Vec<u32> {
+        iter.map(|node| node.id).collect()
+    }
+
+    #[test]
+    fn orders() {
+        let nodes = graph();
+
+        assert_eq!(ids(Dfs::new(nodes[0].clone())), [0, 1, 3, 2]);
+        assert_eq!(ids(DfsPostOrder::new(nodes[0].clone())), [3, 1, 2, 0]);
+        assert_eq!(ids(Bfs::new(nodes[0].clone())), [0, 1, 2, 3]);
+        assert_eq!(ids(Dfs::from_roots([nodes[2].clone(), nodes[1].clone()].iter().cloned())), [2, 3, 0, 1]);
+
+        for node in &nodes {
+            node.edges.borrow_mut().clear();
+        }
+    }
+
+    #[test]
+    fn reachable() {
+        let nodes = graph();
+        nodes[3].edges.borrow_mut().clear();
+
+        let reachable = super::reachable(nodes[1].clone());
+        assert_eq!(reachable.len(), 2);
+        assert!(reachable.contains(&nodes[1]));
+        assert!(reachable.contains(&nodes[3]));
+        assert!(!reachable.contains(&nodes[0]));
+    }
+}
@@ -0,0 +1,325 @@
+//! Side tables keyed by objects which they don't keep alive.
+//!
+//! `WeakKeyMap` attaches values (such as cached layouts, debug names or dirty
+//! flags) to objects behind `Rc` or `Arc` without owning them: it holds only
+//! `Weak` pointers to its keys. Keys are identified by `ObjectId`, which
+//! compares and hashes the same way as `&T` does using `Same` and `RefHash`.
+//! The `Weak` keeps the allocation reserved, so an id of a dropped key can't
+//! match a new object allocated later.
+//!
+//! Entries of dropped keys are ignored by lookups and iteration. They are
+//! removed (and their values dropped) by `purge`, which also happens
+//! automatically during insertion whenever the number of entries doubles
+//! since the previous purge, so the cost is amortized.
+//!
+//! # Example
+//!
+//! ```
+//! use same::weak::WeakKeyMap;
+//! use std::rc::Rc;
+//!
+//! let a = Rc::new("a");
+//! let b = Rc::new("b");
+//! let mut names = WeakKeyMap::new();
+//! names.insert(&a, "first");
+//! names.insert(&b, "second");
+//!
+//! // Lookups accept both references and strong handles.
+//! assert_eq!(names.get(&*a), Some(&"first"));
+//! assert_eq!(names.get(&b), Some(&"second"));
+//! assert_eq!(names.get(&Rc::new("a")), None);
+//!
+//! drop(a);
+//! assert_eq!(names.iter().count(), 1);
+//! names.purge();
+//! assert_eq!(names.len(), 1);
+//! ```
+
+use core::fmt;
+use core::hash::BuildHasher;
+use core::mem;
+use core::ops::Deref;
+use std::collections::hash_map::{self, HashMap, RandomState};
+use std::rc::{self, Rc};
+use std::sync::{self, Arc};
+
+use id::ObjectId;
+
+/// Minimum number of entries before dead ones are purged automatically.
+const MIN_PURGE_THRESHOLD: usize = 32;
+
+/// Shared pointers which have a weak counterpart.
+///
+/// This is implemented for `Rc` and `Arc`, so they can be used as keys of
+/// `WeakKeyMap`.
+pub trait Downgrade: Deref + Sized {
+    /// The weak pointer type.
+    type Weak;
+
+    /// Creates a weak pointer to the object.
+    fn downgrade(&self) -> Self::Weak;
+
+    /// Returns a strong pointer to the object if it's still alive.
+    fn upgrade(weak: &Self::Weak) -> Option<Self>;
+
+    /// Returns `true` if the object wasn't dropped yet.
+    fn is_alive(weak: &Self::Weak) -> bool;
+}
+
+impl<T: ?Sized> Downgrade for Rc<T> {
+    type Weak = rc::Weak<T>;
+
+    fn downgrade(&self) -> Self::Weak {
+        Rc::downgrade(self)
+    }
+
+    fn upgrade(weak: &Self::Weak) -> Option<Self> {
+        weak.upgrade()
+    }
+
+    fn is_alive(weak: &Self::Weak) -> bool {
+        weak.strong_count() > 0
+    }
+}
+
+impl<T: ?Sized> Downgrade for Arc<T> {
+    type Weak = sync::Weak<T>;
+
+    fn downgrade(&self) -> Self::Weak {
+        Arc::downgrade(self)
+    }
+
+    fn upgrade(weak: &Self::Weak) -> Option<Self> {
+        weak.upgrade()
+    }
+
+    fn is_alive(weak: &Self::Weak) -> bool {
+        weak.strong_count() > 0
+    }
+}
+
+struct Entry<K: Downgrade, V> {
+    key: K::Weak,
+    value: V,
+}
+
+impl<K: Downgrade, V> Entry<K, V> {
+    fn is_alive(&self) -> bool {
+        K::is_alive(&self.key)
+    }
+}
+
+/// Map from objects to values which doesn't keep the objects alive.
+///
+/// `K` is the strong pointer type, `Rc<T>` or `Arc<T>`. The map holds only
+/// `Weak<T>`, so the memory of dropped keys isn't released until their
+/// entries are purged.
+pub struct WeakKeyMap<K: Downgrade, V, S = RandomState> {
+    map: HashMap<ObjectId, Entry<K, V>, S>,
+    purge_threshold: usize,
+}
+
+impl<K: Downgrade, V> WeakKeyMap<K, V, RandomState> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K: Downgrade, V, S> WeakKeyMap<K, V, S> {
+    /// Creates an empty map which will use the given hash builder.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        WeakKeyMap { map: HashMap::with_hasher(hash_builder), purge_threshold: MIN_PURGE_THRESHOLD }
+    }
+
+    /// Returns the number of entries, including the ones of dropped keys
+    /// that weren't purged yet.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes all entries from the map.
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    /// Removes entries whose keys were dropped.
+    pub fn purge(&mut self) {
+        self.map.retain(|_, entry| entry.is_alive());
+        self.purge_threshold = MIN_PURGE_THRESHOLD.max(self.map.len() * 2);
+    }
+
+    /// Iterates over the entries of live keys in arbitrary order.
+    ///
+    /// The keys are upgraded to strong pointers, so they stay alive while
+    /// the caller holds them.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter { iter: self.map.values() }
+    }
+}
+
+impl<K: Downgrade, V, S: BuildHasher> WeakKeyMap<K, V, S> {
+    /// Inserts a value for the object the key points to.
+    ///
+    /// If the object already had a value, it's replaced and the old one is
+    /// returned.
+    pub fn insert(&mut self, key: &K, value: V) -> Option<V> {
+        let id = ObjectId::of(key);
+        if let Some(entry) = self.map.get_mut(&id) {
+            // The allocation is reserved by the `Weak`, so the entry belongs
+            // to the same object, which is alive since `key` points to it.
+            return Some(mem::replace(&mut entry.value, value));
+        }
+
+        if self.map.len() >= self.purge_threshold {
+            self.purge();
+        }
+        self.map.insert(id, Entry { key: key.downgrade(), value });
+        None
+    }
+
+    /// Returns a reference to the value associated with the object.
+    pub fn get(&self, key: &K::Target) -> Option<&V> {
+        self.map.get(&ObjectId::of_ref(key)).filter(|entry| entry.is_alive()).map(|entry| &entry.value)
+    }
+
+    /// Returns a mutable reference to the value associated with the object.
+    pub fn get_mut(&mut self, key: &K::Target) -> Option<&mut V> {
+        self.map.get_mut(&ObjectId::of_ref(key)).filter(|entry| entry.is_alive()).map(|entry| &mut entry.value)
+    }
+
+    /// Returns `true` if the map contains a value for the object.
+    pub fn contains_key(&self, key: &K::Target) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Removes the value associated with the object and returns it.
+    pub fn remove(&mut self, key: &K::Target) -> Option<V> {
+        self.map.remove(&ObjectId::of_ref(key)).map(|entry| entry.value)
+    }
+}
+
+impl<K: Downgrade, V, S: Default> Default for WeakKeyMap<K, V, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K: Downgrade + fmt::Debug, V: fmt::Debug, S> fmt::Debug for WeakKeyMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Iterator over the entries of live keys of a `WeakKeyMap`.
+pub struct Iter<'a, K: Downgrade + 'a, V: 'a> {
+    iter: hash_map::Values<'a, ObjectId, Entry<K, V>>,
+}
+
+impl<'a, K: Downgrade, V> Iterator for Iter<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for entry in &mut self.iter {
+            if let Some(key) = K::upgrade(&entry.key) {
+                return Some((key, &entry.value));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WeakKeyMap;
+    use std::rc::Rc;
+    use std::string::{String, ToString};
+    use std::sync::Arc;
+    use std::thread;
+    use std::vec::Vec;
+    use Same;
+
+    #[test]
+    fn lookups() {
+        let a = Rc::new(1);
+        let b = Rc::new(1);
+        let mut map = WeakKeyMap::new();
+
+        assert_eq!(map.insert(&a, "a"), None);
+        assert_eq!(map.insert(&a.clone(), "A"), Some("a"));
+        assert_eq!(map.get(&a), Some(&"A"));
+        assert_eq!(map.get(&*a), Some(&"A"));
+        assert_eq!(map.get(&b), None);
+        assert!(!map.contains_key(&1));
+
+        map.insert(&b, "b");
+        *map.get_mut(&b).unwrap() = "B";
+        assert_eq!(map.remove(&a), Some("A"));
+        assert_eq!(map.remove(&a), None);
+        assert_eq!(map.get(&b), Some(&"B"));
+
+        // The map doesn't keep the keys alive.
+        assert_eq!(Rc::strong_count(&b), 1);
+    }
+
+    #[test]
+    fn slices() {
+        let slice: Rc<[u8]> = Rc::from(&[1, 2, 3][..]);
+        let mut map = WeakKeyMap::new();
+        map.insert(&slice, ());
+
+        assert!(map.contains_key(&slice[..]));
+        // The same address, but a different object.
+        assert!(!map.contains_key(&slice[..2]));
+    }
+
+    #[test]
+    fn dropped_keys() {
+        let value = Rc::new(0);
+        let mut map = WeakKeyMap::new();
+        let keys: Vec<_> = (0..10).map(Rc::new).collect();
+        for key in &keys {
+            map.insert(key, key.to_string());
+        }
+        map.insert(&value, "value".into());
+
+        drop(keys);
+        assert_eq!(map.len(), 11);
+        let live: Vec<_> = map.iter().collect();
+        assert_eq!(live.len(), 1);
+        assert!(live[0].0.same(&value));
+        assert_eq!(live[0].1, "value");
+
+        map.purge();
+        assert_eq!(map.len(), 1);
+
+        // Dead entries are purged automatically on insertion.
+        for _ in 0..100 {
+            map.insert(&Rc::new(0), String::new());
+        }
+        assert!(map.len() < 100);
+        assert_eq!(map.get(&value).map(|s| s.as_str()), Some("value"));
+    }
+
+    #[test]
+    fn threads() {
+        let key = Arc::new(1);
+        let mut map = WeakKeyMap::new();
+        map.insert(&key, 2);
+
+        let map = thread::spawn(move || {
+            assert_eq!(map.get(&key), Some(&2));
+            map
+        }).join().unwrap();
+        assert_eq!(map.iter().count(), 0);
+    }
+}
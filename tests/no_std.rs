@@ -0,0 +1,92 @@
+//! Checks that the smart pointer impls are usable without `std`.
+//!
+//! Run with `cargo test --no-default-features --features alloc`.
+
+#![no_std]
+
+extern crate alloc;
+extern crate same;
+
+use alloc::collections::BTreeSet;
+use alloc::rc::{self, Rc};
+use alloc::sync::{self, Arc};
+use core::hash::Hasher;
+use same::{RefCmp, RefHash, Same};
+
+/// Records the written bytes so that hashes can be compared without `std`.
+#[derive(Default)]
+struct RecordingHasher {
+    bytes: alloc::vec::Vec<u8>,
+}
+
+impl Hasher for RecordingHasher {
+    fn finish(&self) -> u64 {
+        0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+fn ref_hash<T: RefHash>(value: &T) -> alloc::vec::Vec<u8> {
+    let mut hasher = RecordingHasher::default();
+    value.ref_hash(&mut hasher);
+    hasher.bytes
+}
+
+#[test]
+fn rcs() {
+    let a = Rc::new(42);
+    let a_cloned = a.clone();
+    let b = Rc::new(42);
+
+    assert!(a.same(&a_cloned));
+    assert!(!a.same(&b));
+    assert_eq!(ref_hash(&a), ref_hash(&a_cloned));
+    assert_ne!(ref_hash(&a), ref_hash(&b));
+}
+
+#[test]
+fn arcs() {
+    let a: Arc<str> = "foo".into();
+    let a_cloned = a.clone();
+    let b: Arc<str> = "foo".into();
+
+    assert!(a.same(&a_cloned));
+    assert!(!a.same(&b));
+    assert_eq!(ref_hash(&a), ref_hash(&a_cloned));
+}
+
+#[test]
+fn weaks() {
+    let a = Rc::new(42);
+    let a_weak = Rc::downgrade(&a);
+    let b = Arc::new(42);
+    let b_weak = Arc::downgrade(&b);
+
+    assert!(a_weak.same(&Rc::downgrade(&a)));
+    assert!(b_weak.same(&Arc::downgrade(&b)));
+    assert!(rc::Weak::<i32>::new().same(&rc::Weak::new()));
+    assert!(sync::Weak::<i32>::new().same(&sync::Weak::new()));
+    assert!(RefCmp(a.clone()) == RefCmp(a_weak.clone()));
+    assert!(RefCmp(b.clone()) == RefCmp(b_weak.clone()));
+    assert_eq!(ref_hash(&a), ref_hash(&a_weak));
+    assert_eq!(ref_hash(&b), ref_hash(&b_weak));
+}
+
+#[test]
+fn btree_set() {
+    let a = Arc::new(42);
+    let a_weak = Arc::downgrade(&a);
+    let b = Arc::new(42);
+
+    let mut set = BTreeSet::new();
+    assert!(set.insert(RefCmp(a.clone())));
+    assert!(!set.insert(RefCmp(a.clone())));
+    assert!(set.insert(RefCmp(b)));
+
+    let mut weak_set = BTreeSet::new();
+    assert!(weak_set.insert(RefCmp(a_weak)));
+    assert!(!weak_set.insert(RefCmp(Arc::downgrade(&a))));
+}